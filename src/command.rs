@@ -0,0 +1,98 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(test)]
+use std::rc::Rc;
+
+use super::InstallerResult;
+
+// Layer to run external commands, to replace them in tests
+pub trait CommandRunner {
+    // run the command, and return its standard output
+    fn run(&mut self, program: &Path, args: &[String]) -> InstallerResult<String>;
+}
+
+pub struct SystemCommandRunner;
+
+impl CommandRunner for SystemCommandRunner {
+    fn run(&mut self, program: &Path, args: &[String]) -> InstallerResult<String> {
+        let output = Command::new(program).args(args).output()?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed with {}: {}",
+                program.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+// Runner that keeps commands run through it, to show commands run while planning
+pub struct LoggingCommandRunner<'a> {
+    runner: &'a mut dyn CommandRunner,
+    // program and arguments
+    pub commands: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl<'a> LoggingCommandRunner<'a> {
+    pub fn new(runner: &'a mut dyn CommandRunner) -> Self {
+        LoggingCommandRunner {
+            runner,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl CommandRunner for LoggingCommandRunner<'_> {
+    fn run(&mut self, program: &Path, args: &[String]) -> InstallerResult<String> {
+        self.commands.push((program.to_path_buf(), args.to_vec()));
+        self.runner.run(program, args)
+    }
+}
+
+// output of program, or None to fail
+#[cfg(test)]
+type ProgramOutput = (PathBuf, Option<String>);
+
+// Runner that only records commands, to verify them in tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingCommandRunner {
+    commands: Rc<RefCell<Vec<Vec<String>>>>,
+    // programs not in this succeed with empty output
+    outputs: Rc<RefCell<Vec<ProgramOutput>>>,
+}
+
+#[cfg(test)]
+impl RecordingCommandRunner {
+    // commands run, with program at first
+    pub fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.borrow().clone()
+    }
+
+    pub fn set_output(&self, program: &Path, output: Option<&str>) {
+        self.outputs
+            .borrow_mut()
+            .push((program.to_path_buf(), output.map(String::from)));
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for RecordingCommandRunner {
+    fn run(&mut self, program: &Path, args: &[String]) -> InstallerResult<String> {
+        let mut command = vec![program.to_string_lossy().to_string()];
+        command.extend(args.iter().cloned());
+        self.commands.borrow_mut().push(command);
+
+        match self.outputs.borrow().iter().find(|(p, _)| p == program) {
+            Some((_, Some(output))) => Ok(output.clone()),
+            Some((_, None)) => Err(format!("{} failed", program.display()).into()),
+            None => Ok(String::new()),
+        }
+    }
+}
@@ -2,9 +2,15 @@ use std::fmt::{Debug, Display};
 use std::path::PathBuf;
 
 use clap::{App, Arg, ArgMatches, SubCommand};
-use dirs::home_dir;
 
 use crate::execution_mode::ExecutionMode;
+use crate::linux_installer::desktop_entry::validate_desktop_file_id;
+use crate::linux_installer::xdg;
+use crate::macos_installer::bundle::{
+    self, validate_bundle_identifier, validate_bundle_name, BUNDLE_IDENTIFIER, BUNDLE_NAME,
+};
+use crate::macos_installer::{emacsclient, launch_services};
+use crate::stable_path;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum InstallTarget {
@@ -16,6 +22,10 @@ pub enum InstallTarget {
 pub struct Config {
     pub target: InstallTarget,
     pub mode: ExecutionMode,
+    // only show what would be done, without touching the system
+    pub dry_run: bool,
+    // apply changes to existing files without confirmation
+    pub yes: bool,
     pub linux_config: Option<LinuxConfig>,
     pub macos_config: Option<MacOSConfig>,
 }
@@ -27,25 +37,68 @@ impl Display for Config {
 }
 
 impl Config {
+    // Problems in the config that do not prevent installation
+    pub fn warnings(&self) -> Vec<String> {
+        let emacsclient = match (&self.linux_config, &self.macos_config) {
+            (Some(config), _) => &config.emacsclient,
+            (None, Some(config)) => &config.emacsclient,
+            (None, None) => return Vec::new(),
+        };
+        let home = dirs::home_dir();
+        stable_path::warning(&emacsclient.path, home.as_deref())
+            .into_iter()
+            .collect()
+    }
+
     pub fn new(matches: &ArgMatches) -> Result<Config, &'static str> {
         if let Some(matches) = matches.subcommand_matches("linux") {
-            let mut home = home_dir().unwrap();
+            let system = matches.is_present("system");
             let desktop_entry_dir = match matches.value_of("desktop-entry-directory") {
                 Some(str) if !str.is_empty() => PathBuf::from(str),
+                Some(_) | None if system => {
+                    xdg::system_applications_dir().ok_or("Can not detect XDG data directory")?
+                }
                 Some(_) | None => {
-                    home.push(".local/share/applications");
-                    home
+                    xdg::user_applications_dir().ok_or("Can not detect XDG data directory")?
                 }
             };
-            let mode = ExecutionMode::from(matches.value_of("mode").unwrap())
-                .unwrap_or(ExecutionMode::Install);
+            let mode = if matches.is_present("validate-only") {
+                ExecutionMode::Validate
+            } else {
+                ExecutionMode::from(matches.value_of("mode").unwrap())
+                    .unwrap_or(ExecutionMode::Install)
+            };
+            let desktop_file_name = matches.value_of("desktop-file-name").unwrap();
+            validate_desktop_file_id(desktop_file_name)?;
+            let mimeapps_list_path = match matches.value_of("mimeapps-list") {
+                Some(str) => PathBuf::from(str),
+                None if system => {
+                    xdg::system_mimeapps_list().ok_or("Can not detect XDG config directory")?
+                }
+                None => xdg::user_mimeapps_list().ok_or("Can not detect XDG config directory")?,
+            };
 
             Ok(Config {
                 target: InstallTarget::Linux,
                 mode,
+                dry_run: matches.is_present("dry-run"),
+                yes: matches.is_present("yes"),
                 linux_config: Some(LinuxConfig {
                     desktop_entry_directory: desktop_entry_dir,
-                    desktop_file_name: String::from(matches.value_of("desktop-file-name").unwrap()),
+                    desktop_file_name: String::from(desktop_file_name),
+                    entry_name: String::from(matches.value_of("entry-name").unwrap()),
+                    icon: String::from(matches.value_of("icon").unwrap()),
+                    emacsclient: EmacsclientOptions::new(matches),
+                    mimeapps_list_path,
+                    system,
+                    conflict_policy: ConflictPolicy::from(matches.value_of("conflicts").unwrap())
+                        .unwrap_or(ConflictPolicy::Report),
+                    mime_cache_updater: MimeCacheUpdater::from(
+                        matches.value_of("mime-cache").unwrap(),
+                    )
+                    .unwrap_or(MimeCacheUpdater::Builtin),
+                    validate_path: matches.value_of("path").map(PathBuf::from),
+                    search_paths: xdg::SearchPaths::from_env(),
                 }),
                 macos_config: None,
             })
@@ -53,17 +106,49 @@ impl Config {
             let mode = ExecutionMode::from(matches.value_of("mode").unwrap())
                 .unwrap_or(ExecutionMode::Install);
 
-            let emacsclient_path = matches.value_of("emacsclient-path").unwrap_or("");
-            if emacsclient_path.is_empty() {
-                return Err("Need emacsclient-path");
-            }
+            // search emacsclient on install if not given
+            let emacsclient_search = match matches.value_of("emacsclient-path") {
+                Some(str) if !str.is_empty() => None,
+                Some(_) | None => Some(emacsclient::Search::from_env()),
+            };
+
+            let applications_dir = match matches.value_of("applications-dir") {
+                Some(str) => PathBuf::from(str),
+                None if matches.is_present("user") => dirs::home_dir()
+                    .ok_or("Can not detect home directory")?
+                    .join("Applications"),
+                None => PathBuf::from("/Applications"),
+            };
+            let launch_services_plist = match matches.value_of("launch-services-plist") {
+                Some(str) => PathBuf::from(str),
+                None => launch_services::secure_plist_path(
+                    &dirs::home_dir().ok_or("Can not detect home directory")?,
+                ),
+            };
+            let bundle_name = matches.value_of("bundle-name").unwrap();
+            validate_bundle_name(bundle_name)?;
+            let bundle_identifier = matches.value_of("bundle-identifier").unwrap();
+            validate_bundle_identifier(bundle_identifier)?;
 
             Ok(Config {
                 target: InstallTarget::MacOS,
                 mode,
+                dry_run: matches.is_present("dry-run"),
+                yes: matches.is_present("yes"),
                 linux_config: None,
                 macos_config: Some(MacOSConfig {
-                    emacsclient_path: PathBuf::from(emacsclient_path),
+                    emacsclient: EmacsclientOptions::new(matches),
+                    activate: if matches.is_present("no-activate") {
+                        None
+                    } else {
+                        matches.value_of("activate").map(String::from)
+                    },
+                    applications_dir,
+                    bundle_name: String::from(bundle_name),
+                    bundle_identifier: String::from(bundle_identifier),
+                    launch_services_plist,
+                    background_only: matches.is_present("background"),
+                    emacsclient_search,
                 }),
             })
         } else {
@@ -83,18 +168,71 @@ pub fn application_definition<'a, 'b>() -> App<'a, 'b> {
                         .default_value("")
                         .help("A full path of directory to save desktop entry"),
                 )
+                .arg(
+                    Arg::with_name("system")
+                        .long("system")
+                        .help("Install for all users into a directory in $XDG_DATA_DIRS. Need root permission"),
+                )
                 .arg(
                     Arg::with_name("mode")
                         .default_value("install")
-                        .possible_values(&["install", "uninstall"])
-                        .help("execute mode"),
+                        .possible_values(&["install", "uninstall", "resolve", "validate", "repair"])
+                        .help("execute mode. resolve shows which desktop file handles org-protocol, validate checks desktop file"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .help("Desktop file to check in validate mode. Default is the desktop entry to be installed"),
+                )
+                .arg(
+                    Arg::with_name("validate-only")
+                        .long("validate-only")
+                        .help("Only validate the desktop entry to be installed"),
                 )
                 .arg(
                     Arg::with_name("desktop-file-name")
                         .short("f")
                         .default_value("org-protocol.desktop")
                         .help("Name of desktop file for org-protocol"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("entry-name")
+                        .long("entry-name")
+                        .value_name("NAME")
+                        .default_value("Org-Protocol")
+                        .help("Value of Name key in desktop entry"),
+                )
+                .arg(
+                    Arg::with_name("icon")
+                        .long("icon")
+                        .value_name("ICON")
+                        .default_value("emacs-icon")
+                        .help("Value of Icon key in desktop entry"),
+                )
+                .arg(
+                    Arg::with_name("conflicts")
+                        .long("conflicts")
+                        .value_name("POLICY")
+                        .default_value("report")
+                        .possible_values(&["report", "hide", "shadow"])
+                        .help("How to treat other desktop files handling org-protocol. hide overrides them with Hidden=true, shadow overrides them without org-protocol"),
+                )
+                .arg(
+                    Arg::with_name("mime-cache")
+                        .long("mime-cache")
+                        .value_name("UPDATER")
+                        .default_value("builtin")
+                        .possible_values(&["builtin", "external"])
+                        .help("How to regenerate mimeinfo.cache. external runs update-desktop-database"),
+                )
+                .arg(
+                    Arg::with_name("mimeapps-list")
+                        .long("mimeapps-list")
+                        .value_name("PATH")
+                        .help("A full path of mimeapps.list to register org-protocol. Default is $XDG_CONFIG_HOME/mimeapps.list"),
+                )
+                .args(&emacsclient_arguments())
+                .args(&plan_arguments()),
         )
         .subcommand(
             SubCommand::with_name("macos")
@@ -102,24 +240,242 @@ pub fn application_definition<'a, 'b>() -> App<'a, 'b> {
                 .arg(
                     Arg::with_name("mode")
                         .default_value("install")
-                        .possible_values(&["install", "uninstall"])
-                        .help("A full path of directory to save desktop entry"),
+                        .possible_values(&["install", "uninstall", "repair"])
+                        .help("execute mode. repair rewrites version pinned emacsclient of the installed application"),
                 )
                 .arg(
                     Arg::with_name("emacsclient-path")
                         .long("emacsclient-path")
                         .value_name("PATH")
-                        .required(true)
-                        .help("Full path of emacsclient in this machine"),
-                ),
+                        .help("Full path of emacsclient in this machine. Search known locations, such as PATH, Emacs.app, Homebrew, MacPorts and Nix, if not given"),
+                )
+                .arg(
+                    Arg::with_name("activate")
+                        .long("activate")
+                        .value_name("APP")
+                        .default_value("Emacs")
+                        .help("Name or full path of application to activate after opening URL, such as Emacs-mac or /Applications/Emacs.app"),
+                )
+                .arg(
+                    Arg::with_name("no-activate")
+                        .long("no-activate")
+                        .help("Do not activate any application after opening URL"),
+                )
+                .arg(
+                    Arg::with_name("user")
+                        .long("user")
+                        .help("Install into ~/Applications, that does not need admin permission"),
+                )
+                .arg(
+                    Arg::with_name("applications-dir")
+                        .long("applications-dir")
+                        .value_name("DIR")
+                        .conflicts_with("user")
+                        .help("A full path of directory to install the application bundle into. Default is /Applications"),
+                )
+                .arg(
+                    Arg::with_name("bundle-name")
+                        .long("bundle-name")
+                        .value_name("NAME")
+                        .default_value(BUNDLE_NAME)
+                        .help("Name of the application bundle, without .app"),
+                )
+                .arg(
+                    Arg::with_name("bundle-identifier")
+                        .long("bundle-identifier")
+                        .value_name("ID")
+                        .default_value(BUNDLE_IDENTIFIER)
+                        .help("CFBundleIdentifier of the application"),
+                )
+                .arg(
+                    Arg::with_name("background")
+                        .long("background")
+                        .help("Run the client application in background, without Dock icon and app switcher"),
+                )
+                .arg(
+                    Arg::with_name("launch-services-plist")
+                        .long("launch-services-plist")
+                        .value_name("PATH")
+                        .help("A full path of preferences of LaunchServices to register default handler of org-protocol. Default is ~/Library/Preferences/com.apple.LaunchServices/com.apple.launchservices.secure.plist"),
+                )
+                .args(&emacsclient_option_arguments())
+                .args(&plan_arguments()),
         )
 }
 
+// options how to apply changes to the system
+fn plan_arguments<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Show files to be created, changed or deleted and commands to be run, without touching the system"),
+        Arg::with_name("yes")
+            .long("yes")
+            .short("y")
+            .help("Change existing files without confirmation. Diff of them is still shown"),
+    ]
+}
+
+fn emacsclient_arguments<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = vec![Arg::with_name("emacsclient-path")
+        .long("emacsclient-path")
+        .value_name("PATH")
+        .default_value("emacsclient")
+        .help("Path of emacsclient in this machine")];
+    args.extend(emacsclient_option_arguments());
+
+    args
+}
+
+// options of emacsclient other than the path of it
+fn emacsclient_option_arguments<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("socket-name")
+            .long("socket-name")
+            .value_name("NAME")
+            .conflicts_with("server-file")
+            .help("Name of Emacs server socket passed to emacsclient"),
+        Arg::with_name("server-file")
+            .long("server-file")
+            .value_name("FILE")
+            .help("Server file passed to emacsclient"),
+        Arg::with_name("create-frame")
+            .long("create-frame")
+            .short("c")
+            .help("Make emacsclient create new frame"),
+        Arg::with_name("no-wait")
+            .long("no-wait")
+            .short("n")
+            .help("Do not make emacsclient wait for the server"),
+        Arg::with_name("alternate-editor")
+            .long("alternate-editor")
+            .value_name("EDITOR")
+            .help("Editor to run if Emacs server is not running"),
+    ]
+}
+
+// options to invoke emacsclient
+#[derive(Debug, PartialEq, Eq)]
+pub struct EmacsclientOptions {
+    pub path: PathBuf,
+    pub socket_name: Option<String>,
+    pub server_file: Option<PathBuf>,
+    pub create_frame: bool,
+    pub no_wait: bool,
+    pub alternate_editor: Option<String>,
+}
+
+impl Default for EmacsclientOptions {
+    fn default() -> Self {
+        EmacsclientOptions {
+            path: PathBuf::from("emacsclient"),
+            socket_name: None,
+            server_file: None,
+            create_frame: false,
+            no_wait: false,
+            alternate_editor: None,
+        }
+    }
+}
+
+impl EmacsclientOptions {
+    fn new(matches: &ArgMatches) -> Self {
+        EmacsclientOptions {
+            path: PathBuf::from(
+                matches
+                    .value_of("emacsclient-path")
+                    .unwrap_or("emacsclient"),
+            ),
+            socket_name: matches.value_of("socket-name").map(String::from),
+            server_file: matches.value_of("server-file").map(PathBuf::from),
+            create_frame: matches.is_present("create-frame"),
+            no_wait: matches.is_present("no-wait"),
+            alternate_editor: matches.value_of("alternate-editor").map(String::from),
+        }
+    }
+
+    // arguments to give emacsclient, without path of emacsclient itself
+    pub fn arguments(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(name) = &self.socket_name {
+            args.push(format!("--socket-name={}", name));
+        }
+        if let Some(file) = &self.server_file {
+            args.push(format!("--server-file={}", file.to_string_lossy()));
+        }
+        if self.create_frame {
+            args.push(String::from("--create-frame"));
+        }
+        if self.no_wait {
+            args.push(String::from("--no-wait"));
+        }
+        if let Some(editor) = &self.alternate_editor {
+            args.push(format!("--alternate-editor={}", editor));
+        }
+
+        args
+    }
+}
+
+// how to treat other desktop files that handle org-protocol
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // only report them
+    Report,
+    // override them with Hidden=true
+    Hide,
+    // override them with copies that do not have org-protocol in MimeType
+    Shadow,
+}
+
+impl ConflictPolicy {
+    pub fn from(str: &str) -> Option<ConflictPolicy> {
+        match str {
+            "report" => Some(ConflictPolicy::Report),
+            "hide" => Some(ConflictPolicy::Hide),
+            "shadow" => Some(ConflictPolicy::Shadow),
+            _ => None,
+        }
+    }
+}
+
+// how to regenerate mimeinfo.cache after changing desktop entry directory
+#[derive(Debug, PartialEq, Eq)]
+pub enum MimeCacheUpdater {
+    // rebuild the cache by this program
+    Builtin,
+    // run update-desktop-database
+    External,
+}
+
+impl MimeCacheUpdater {
+    pub fn from(str: &str) -> Option<MimeCacheUpdater> {
+        match str {
+            "builtin" => Some(MimeCacheUpdater::Builtin),
+            "external" => Some(MimeCacheUpdater::External),
+            _ => None,
+        }
+    }
+}
+
 // configuration for linux
 #[derive(Debug, PartialEq, Eq)]
 pub struct LinuxConfig {
     pub desktop_entry_directory: PathBuf,
     pub desktop_file_name: String,
+    pub entry_name: String,
+    pub icon: String,
+    pub emacsclient: EmacsclientOptions,
+    pub mimeapps_list_path: PathBuf,
+    // install for all users
+    pub system: bool,
+    pub conflict_policy: ConflictPolicy,
+    pub mime_cache_updater: MimeCacheUpdater,
+    // desktop file to check in validate mode
+    pub validate_path: Option<PathBuf>,
+    // directories to look up desktop entries of other applications
+    pub search_paths: xdg::SearchPaths,
 }
 
 impl LinuxConfig {
@@ -135,9 +491,11 @@ impl Display for LinuxConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "LinuxConfig{{desktop_entry_directory: {}, desktop_file_name: {}}}",
+            "LinuxConfig{{desktop_entry_directory: {}, desktop_file_name: {}, entry_name: {}, icon: {}}}",
             self.desktop_entry_directory.to_str().unwrap(),
-            self.desktop_file_name
+            self.desktop_file_name,
+            self.entry_name,
+            self.icon
         )
     }
 }
@@ -145,7 +503,23 @@ impl Display for LinuxConfig {
 // configuration for macOS
 #[derive(Debug, PartialEq, Eq)]
 pub struct MacOSConfig {
-    pub emacsclient_path: PathBuf,
+    pub emacsclient: EmacsclientOptions,
+    // application to activate after opening URL, or None to skip activation
+    pub activate: Option<String>,
+    pub applications_dir: PathBuf,
+    pub bundle_name: String,
+    pub bundle_identifier: String,
+    pub launch_services_plist: PathBuf,
+    // set LSUIElement and LSBackgroundOnly into Info.plist
+    pub background_only: bool,
+    // where to search emacsclient, or None to use path in options as is
+    pub emacsclient_search: Option<emacsclient::Search>,
+}
+
+impl MacOSConfig {
+    pub fn bundle_path(&self) -> PathBuf {
+        bundle::bundle_path(&self.applications_dir, &self.bundle_name)
+    }
 }
 
 impl Display for MacOSConfig {
@@ -159,7 +533,13 @@ mod test {
     mod config {
         use std::path::PathBuf;
 
-        use crate::config::{application_definition, Config, LinuxConfig};
+        use crate::config::{
+            application_definition, Config, ConflictPolicy, EmacsclientOptions, LinuxConfig,
+            MacOSConfig, MimeCacheUpdater,
+        };
+        use crate::execution_mode::ExecutionMode;
+        use crate::linux_installer::xdg;
+        use crate::macos_installer::{emacsclient, launch_services};
 
         #[test]
         fn get_valid_config() {
@@ -171,27 +551,66 @@ mod test {
             let actual = Config::new(&matches);
 
             // verify
-            let mut home = dirs::home_dir().unwrap();
-            home.push(".local/share/applications");
+            let home = xdg::user_applications_dir().unwrap();
 
             assert_eq!(
                 actual,
                 Ok(Config {
                     target: crate::config::InstallTarget::Linux,
                     mode: crate::execution_mode::ExecutionMode::Install,
+                    dry_run: false,
+                    yes: false,
                     linux_config: Some(LinuxConfig {
                         desktop_entry_directory: home,
-                        desktop_file_name: String::from("org-protocol.desktop")
+                        desktop_file_name: String::from("org-protocol.desktop"),
+                        entry_name: String::from("Org-Protocol"),
+                        icon: String::from("emacs-icon"),
+                        emacsclient: EmacsclientOptions::default(),
+                        mimeapps_list_path: xdg::user_mimeapps_list().unwrap(),
+                        system: false,
+                        conflict_policy: ConflictPolicy::Report,
+                        mime_cache_updater: MimeCacheUpdater::Builtin,
+                        validate_path: None,
+                        search_paths: xdg::SearchPaths::from_env(),
                     }),
                     macos_config: None
                 })
             )
         }
 
+        #[test]
+        fn get_plan_options_for_both_targets() {
+            // arrange
+            let linux =
+                application_definition().get_matches_from(vec!["", "linux", "--dry-run", "--yes"]);
+            let macos = application_definition().get_matches_from(vec![
+                "",
+                "macos",
+                "uninstall",
+                "--emacsclient-path",
+                "/usr/local/bin/emacsclient",
+                "--dry-run",
+            ]);
+
+            // do
+            let linux = Config::new(&linux).unwrap();
+            let macos = Config::new(&macos).unwrap();
+
+            // verify
+            assert!(linux.dry_run);
+            assert!(linux.yes);
+            assert!(macos.dry_run);
+            assert!(!macos.yes);
+            assert_eq!(macos.mode, crate::execution_mode::ExecutionMode::Uninstall);
+        }
+
         mod linux_config {
             use std::path::PathBuf;
 
-            use crate::config::LinuxConfig;
+            use crate::config::{
+                ConflictPolicy, EmacsclientOptions, LinuxConfig, MimeCacheUpdater,
+            };
+            use crate::linux_installer::xdg;
 
             #[test]
             fn get_desktop_file() {
@@ -199,6 +618,15 @@ mod test {
                 let config = LinuxConfig {
                     desktop_entry_directory: PathBuf::from("directory"),
                     desktop_file_name: String::from("file.desktop"),
+                    entry_name: String::from("Org-Protocol"),
+                    icon: String::from("emacs-icon"),
+                    emacsclient: EmacsclientOptions::default(),
+                    mimeapps_list_path: PathBuf::from("mimeapps.list"),
+                    system: false,
+                    conflict_policy: ConflictPolicy::Report,
+                    mime_cache_updater: MimeCacheUpdater::Builtin,
+                    validate_path: None,
+                    search_paths: xdg::SearchPaths::from_env(),
                 };
 
                 // do
@@ -233,15 +661,255 @@ mod test {
                 Ok(Config {
                     target: crate::config::InstallTarget::Linux,
                     mode: crate::execution_mode::ExecutionMode::Install,
+                    dry_run: false,
+                    yes: false,
                     linux_config: Some(LinuxConfig {
                         desktop_entry_directory: PathBuf::from("directory"),
-                        desktop_file_name: String::from("file.desktop")
+                        desktop_file_name: String::from("file.desktop"),
+                        entry_name: String::from("Org-Protocol"),
+                        icon: String::from("emacs-icon"),
+                        emacsclient: EmacsclientOptions::default(),
+                        mimeapps_list_path: xdg::user_mimeapps_list().unwrap(),
+                        system: false,
+                        conflict_policy: ConflictPolicy::Report,
+                        mime_cache_updater: MimeCacheUpdater::Builtin,
+                        validate_path: None,
+                        search_paths: xdg::SearchPaths::from_env(),
                     }),
                     macos_config: None
                 })
             )
         }
 
+        #[test]
+        fn get_emacsclient_options_for_linux() {
+            // arrange
+            let args = vec![
+                "",
+                "linux",
+                "--emacsclient-path",
+                "/nix/profile/bin/emacsclient",
+                "--socket-name",
+                "work",
+                "-c",
+                "--no-wait",
+                "--alternate-editor",
+                "",
+            ];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().linux_config.unwrap();
+
+            // verify
+            assert_eq!(
+                actual.emacsclient,
+                EmacsclientOptions {
+                    path: PathBuf::from("/nix/profile/bin/emacsclient"),
+                    socket_name: Some(String::from("work")),
+                    server_file: None,
+                    create_frame: true,
+                    no_wait: true,
+                    alternate_editor: Some(String::from("")),
+                }
+            );
+            assert_eq!(
+                actual.emacsclient.arguments(),
+                vec![
+                    "--socket-name=work",
+                    "--create-frame",
+                    "--no-wait",
+                    "--alternate-editor="
+                ]
+            )
+        }
+
+        #[test]
+        fn get_error_if_invalid_desktop_file_name() {
+            // arrange
+            let args = vec!["", "linux", "-f", "org-protocol"];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches);
+
+            // verify
+            assert_eq!(actual, Err("Desktop file name must end with .desktop"))
+        }
+
+        #[test]
+        fn use_system_directories_in_system_mode() {
+            // arrange
+            let args = vec!["", "linux", "--system"];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().linux_config.unwrap();
+
+            // verify
+            assert!(actual.system);
+            assert_eq!(
+                Some(actual.desktop_entry_directory),
+                xdg::system_applications_dir()
+            );
+            assert_eq!(Some(actual.mimeapps_list_path), xdg::system_mimeapps_list());
+        }
+
+        #[test]
+        fn get_validate_mode_with_path() {
+            // arrange
+            let args = vec!["", "linux", "validate", "foo.desktop"];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap();
+
+            // verify
+            assert_eq!(actual.mode, ExecutionMode::Validate);
+            assert_eq!(
+                actual.linux_config.unwrap().validate_path,
+                Some(PathBuf::from("foo.desktop"))
+            );
+        }
+
+        #[test]
+        fn get_validate_mode_from_flag() {
+            // arrange
+            let args = vec!["", "linux", "--validate-only"];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap();
+
+            // verify
+            assert_eq!(actual.mode, ExecutionMode::Validate);
+        }
+
+        #[test]
+        fn get_valid_macos_config() {
+            // arrange
+            let args = vec![
+                "",
+                "macos",
+                "--emacsclient-path",
+                "/usr/local/bin/emacsclient",
+                "--applications-dir",
+                "/Users/foo/Applications",
+                "--bundle-name",
+                "Org Protocol",
+                "--background",
+            ];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().macos_config;
+
+            // verify
+            assert_eq!(
+                actual,
+                Some(MacOSConfig {
+                    emacsclient: EmacsclientOptions {
+                        path: PathBuf::from("/usr/local/bin/emacsclient"),
+                        ..EmacsclientOptions::default()
+                    },
+                    activate: Some(String::from("Emacs")),
+                    applications_dir: PathBuf::from("/Users/foo/Applications"),
+                    bundle_name: String::from("Org Protocol"),
+                    bundle_identifier: String::from("org.orgroam.OrgProtocolClient"),
+                    launch_services_plist: launch_services::secure_plist_path(
+                        &dirs::home_dir().unwrap()
+                    ),
+                    background_only: true,
+                    emacsclient_search: None,
+                })
+            );
+            assert_eq!(
+                actual.unwrap().bundle_path(),
+                PathBuf::from("/Users/foo/Applications/Org Protocol.app")
+            );
+        }
+
+        #[test]
+        fn search_emacsclient_if_not_given_on_macos() {
+            // arrange
+            let args = vec!["", "macos"];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().macos_config.unwrap();
+
+            // verify
+            assert_eq!(
+                actual.emacsclient_search,
+                Some(emacsclient::Search::from_env())
+            );
+        }
+
+        #[test]
+        fn skip_activation_on_macos() {
+            // arrange
+            let args = vec![
+                "",
+                "macos",
+                "--emacsclient-path",
+                "emacsclient",
+                "--socket-name",
+                "work",
+                "--no-activate",
+            ];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().macos_config.unwrap();
+
+            // verify
+            assert_eq!(actual.activate, None);
+            assert_eq!(actual.emacsclient.socket_name, Some(String::from("work")));
+        }
+
+        #[test]
+        fn get_error_if_invalid_bundle_identifier() {
+            // arrange
+            let args = vec![
+                "",
+                "macos",
+                "--emacsclient-path",
+                "emacsclient",
+                "--bundle-identifier",
+                "org.example/client",
+            ];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches);
+
+            // verify
+            assert_eq!(
+                actual,
+                Err("Bundle identifier must contain only alphanumerics, hyphens and periods")
+            )
+        }
+
+        #[test]
+        fn warn_version_pinned_emacsclient() {
+            // arrange
+            let args = vec![
+                "",
+                "linux",
+                "--emacsclient-path",
+                "/nix/store/0123abcd-emacs-29.1/bin/emacsclient",
+            ];
+            let matches = application_definition().get_matches_from(args);
+
+            // do
+            let actual = Config::new(&matches).unwrap().warnings();
+
+            // verify
+            assert_eq!(actual.len(), 1);
+            assert!(actual[0]
+                .starts_with("/nix/store/0123abcd-emacs-29.1/bin/emacsclient contains version"));
+        }
+
         #[test]
         fn get_error_if_invalid_os() {
             // arrange
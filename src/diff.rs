@@ -0,0 +1,199 @@
+use std::fmt::Write;
+
+// lines around changes in a hunk
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    // index of line in old and new
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// Edit script from old lines to new lines, based on longest common subsequence
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j] is length of LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            ops.push(Op::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // deletion first, as diff does
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+
+    ops
+}
+
+// Range in hunk header. Count is omitted if it is 1, and start is the line before the range
+// if the range is empty, as diff does.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// Write the line with the prefix. Line without newline is the last one, and marked as diff
+// does.
+fn push_line(body: &mut String, prefix: char, line: &str) {
+    body.push(prefix);
+    body.push_str(line);
+    if !line.ends_with('\n') {
+        body.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+// Unified diff from old to new, as `diff -u` prints. Return empty string if both are same.
+// Lines are compared with their line endings, to show changes only in them.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // ranges of ops in hunks. Changes closer than twice of context are in the same hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changes {
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        // position of the hunk in old and new, that is count of lines before it
+        let (mut old_start, mut new_start) = (0, 0);
+        for op in &ops[..start] {
+            match op {
+                Op::Equal(..) => {
+                    old_start += 1;
+                    new_start += 1;
+                }
+                Op::Delete(_) => old_start += 1,
+                Op::Insert(_) => new_start += 1,
+            }
+        }
+
+        let mut body = String::new();
+        let (mut old_count, mut new_count) = (0, 0);
+        for op in &ops[start..end] {
+            match *op {
+                Op::Equal(i, _) => {
+                    push_line(&mut body, ' ', old_lines[i]);
+                    old_count += 1;
+                    new_count += 1;
+                }
+                Op::Delete(i) => {
+                    push_line(&mut body, '-', old_lines[i]);
+                    old_count += 1;
+                }
+                Op::Insert(j) => {
+                    push_line(&mut body, '+', new_lines[j]);
+                    new_count += 1;
+                }
+            }
+        }
+
+        let _ = writeln!(
+            diff,
+            "@@ -{} +{} @@",
+            range(old_start, old_count),
+            range(new_start, new_count)
+        );
+        diff.push_str(&body);
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn show_changes_with_context() {
+        // arrange
+        let old = "[Default Applications]\na=1\nb=2\nc=3\nd=4\ne=5\nf=6\ng=7\nh=8\ni=9\n";
+        let new = "[Default Applications]\na=1\nb=2\nc=3\nd=4\ne=5\nf=6\ng=7\nh=8\ni=10\nj=11\n";
+
+        // do
+        let actual = unified_diff(old, new, "old", "new");
+
+        // verify
+        assert_eq!(
+            actual,
+            "--- old\n+++ new\n@@ -7,4 +7,5 @@\n f=6\n g=7\n h=8\n-i=9\n+i=10\n+j=11\n"
+        );
+        assert_eq!(unified_diff(old, old, "old", "new"), "");
+    }
+
+    #[test]
+    fn show_change_of_newline_at_end_of_file() {
+        // arrange
+        let old = "[Default Applications]\na=1";
+        let new = "[Default Applications]\na=1\n";
+
+        // do
+        let actual = unified_diff(old, new, "old", "new");
+
+        // verify
+        assert_eq!(
+            actual,
+            "--- old\n+++ new\n@@ -1,2 +1,2 @@\n [Default Applications]\n-a=1\n\\ No newline at end of file\n+a=1\n"
+        );
+        assert_ne!(
+            unified_diff(new, &new.replace('\n', "\r\n"), "old", "new"),
+            ""
+        );
+    }
+
+    #[test]
+    fn show_removed_file() {
+        // arrange
+        let old = "[Desktop Entry]\nName=Org-Protocol\n";
+
+        // do
+        let actual = unified_diff(old, "", "org-protocol.desktop", "/dev/null");
+
+        // verify
+        assert_eq!(
+            actual,
+            "--- org-protocol.desktop\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-[Desktop Entry]\n-Name=Org-Protocol\n"
+        );
+    }
+}
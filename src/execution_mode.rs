@@ -2,6 +2,12 @@
 pub enum ExecutionMode {
     Install,
     Uninstall,
+    // look up the handler of org-protocol without changing anything
+    Resolve,
+    // validate desktop entry without installing it
+    Validate,
+    // rewrite emacsclient of installed handler to the path not changed on upgrade
+    Repair,
 }
 
 impl ExecutionMode {
@@ -9,6 +15,9 @@ impl ExecutionMode {
         match str {
             "install" => Some(ExecutionMode::Install),
             "uninstall" => Some(ExecutionMode::Uninstall),
+            "resolve" => Some(ExecutionMode::Resolve),
+            "validate" => Some(ExecutionMode::Validate),
+            "repair" => Some(ExecutionMode::Repair),
             _ => None,
         }
     }
@@ -39,6 +48,36 @@ mod test {
         assert_eq!(actual, Some(ExecutionMode::Uninstall))
     }
 
+    #[test]
+    fn convert_resolve_from_string() {
+        // arrange
+        // do
+        let actual = ExecutionMode::from("resolve");
+
+        // verify
+        assert_eq!(actual, Some(ExecutionMode::Resolve))
+    }
+
+    #[test]
+    fn convert_validate_from_string() {
+        // arrange
+        // do
+        let actual = ExecutionMode::from("validate");
+
+        // verify
+        assert_eq!(actual, Some(ExecutionMode::Validate))
+    }
+
+    #[test]
+    fn convert_repair_from_string() {
+        // arrange
+        // do
+        let actual = ExecutionMode::from("repair");
+
+        // verify
+        assert_eq!(actual, Some(ExecutionMode::Repair))
+    }
+
     #[test]
     fn return_error_if_can_not_convert() {
         // arrange
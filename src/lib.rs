@@ -1,29 +1,71 @@
 use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::PermissionsExt;
 
+use command::{CommandRunner, SystemCommandRunner};
 use config::Config;
+use plan::{Action, Plan};
+use transaction::Transaction;
 
+pub mod command;
 pub mod config;
+pub mod diff;
 pub mod execution_mode;
 pub mod linux_installer;
 pub mod macos_installer;
+pub mod plan;
+pub mod stable_path;
+pub mod transaction;
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    for warning in config.warnings() {
+        println!("warning: {}", warning);
+    }
+
     let mode = config.mode;
+    let dry_run = config.dry_run;
+    let yes = config.yes;
     match config.target {
         config::InstallTarget::Linux => {
             let config = config.linux_config.unwrap();
             match mode {
                 execution_mode::ExecutionMode::Install => {
-                    linux_installer::new(config).install()?;
-                    println!("Installation process finished successfully.\n");
-                    println!("You should do something to use org-roam-protocol");
-                    println!("  1: Enable org-roam-protocol in your Emacs's init file.");
-                    println!("    (require 'org-roam-protocol)");
-                    println!("  2: Create the bookmarklet in your browser written at https://www.orgroam.com/manual.html#The-roam_002dref-protocol");
+                    if apply_plan(&linux_installer::new(config).install()?, dry_run, yes)? {
+                        println!("Installation process finished successfully.\n");
+                        println!("You should do something to use org-roam-protocol");
+                        println!("  1: Enable org-roam-protocol in your Emacs's init file.");
+                        println!("    (require 'org-roam-protocol)");
+                        println!("  2: Create the bookmarklet in your browser written at https://www.orgroam.com/manual.html#The-roam_002dref-protocol");
+                    }
                 }
                 execution_mode::ExecutionMode::Uninstall => {
-                    linux_installer::new(config).uninstall()?;
-                    println!("Uninstall process finished successfully");
+                    if apply_plan(&linux_installer::new(config).uninstall()?, dry_run, yes)? {
+                        println!("Uninstall process finished successfully");
+                    }
+                }
+                execution_mode::ExecutionMode::Repair => {
+                    if apply_plan(&linux_installer::new(config).repair()?, dry_run, yes)? {
+                        println!("Repair process finished successfully");
+                    }
+                }
+                execution_mode::ExecutionMode::Resolve => {
+                    let resolution = linux_installer::resolve::resolve(
+                        &config.search_paths,
+                        linux_installer::desktop_entry::ORG_PROTOCOL_MIME_TYPE,
+                    );
+                    print!("{}", resolution);
+                }
+                execution_mode::ExecutionMode::Validate => {
+                    let issues = linux_installer::validate(&config)?;
+                    for issue in &issues {
+                        println!("{}", issue);
+                    }
+
+                    if linux_installer::validator::has_error(&issues) {
+                        return Err("Desktop entry has errors".into());
+                    }
+                    println!("Desktop entry is valid");
                 }
             }
 
@@ -35,16 +77,28 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
 
             match mode {
                 execution_mode::ExecutionMode::Install => {
-                    macos_installer::new(config).install()?;
-                    println!("Installation process finished successfully.\n");
-                    println!("You should do something to use org-roam-protocol");
-                    println!("  1: Enable org-roam-protocol in your Emacs's init file.");
-                    println!("    (require 'org-roam-protocol)");
-                    println!("  2: Create the bookmarklet in your browser written at https://www.orgroam.com/manual.html#The-roam_002dref-protocol");
+                    if apply_plan(&macos_installer::new(config).install()?, dry_run, yes)? {
+                        println!("Installation process finished successfully.\n");
+                        println!("You should do something to use org-roam-protocol");
+                        println!("  1: Enable org-roam-protocol in your Emacs's init file.");
+                        println!("    (require 'org-roam-protocol)");
+                        println!("  2: Create the bookmarklet in your browser written at https://www.orgroam.com/manual.html#The-roam_002dref-protocol");
+                        println!("\nIf org-protocol still opens other application, log out and log in again to reload LaunchServices.");
+                    }
                 }
                 execution_mode::ExecutionMode::Uninstall => {
-                    macos_installer::new(config).uninstall()?;
-                    println!("Uninstall process finished successfully");
+                    if apply_plan(&macos_installer::new(config).uninstall()?, dry_run, yes)? {
+                        println!("Uninstall process finished successfully");
+                    }
+                }
+                execution_mode::ExecutionMode::Repair => {
+                    if apply_plan(&macos_installer::new(config).repair()?, dry_run, yes)? {
+                        println!("Repair process finished successfully");
+                    }
+                }
+                execution_mode::ExecutionMode::Resolve
+                | execution_mode::ExecutionMode::Validate => {
+                    return Err(format!("{:?} mode is supported only for linux", mode).into());
                 }
             }
 
@@ -55,7 +109,289 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
 
 type InstallerResult<T> = Result<T, Box<dyn Error>>;
 
+// Installers make plan of actions without touching the system, and execute applies it.
 pub trait RoamProtocolInstaller {
-    fn install(&mut self) -> InstallerResult<()>;
-    fn uninstall(&mut self) -> InstallerResult<()>;
+    fn install(&mut self) -> InstallerResult<Plan>;
+    fn uninstall(&mut self) -> InstallerResult<Plan>;
+    // rewrite version pinned emacsclient of installed handler to stable path
+    fn repair(&mut self) -> InstallerResult<Plan>;
+}
+
+// Apply the plan after preflight check, or only show it in dry run. Diff of existing files is
+// shown, and they are changed only after confirmation. Return true if applied.
+fn apply_plan(plan: &Plan, dry_run: bool, yes: bool) -> InstallerResult<bool> {
+    if dry_run {
+        print!("{}", plan.dry_run_report());
+        for change in &plan.file_changes()? {
+            print!("{}", change.diff());
+        }
+        // fail after the report, to show what would fail
+        plan.preflight()?;
+        return Ok(false);
+    }
+
+    plan.preflight()?;
+    let changes = plan.file_changes()?;
+
+    for change in &changes {
+        print!("{}", change.diff());
+    }
+    if !changes.is_empty() && !yes && !confirm(&mut io::stdin().lock())? {
+        return Err(
+            "Aborted without changes. Run with --yes to change existing files without confirmation"
+                .into(),
+        );
+    }
+    execute(plan, &mut SystemCommandRunner)?;
+    Ok(true)
+}
+
+// Ask to change existing files. Anything other than y or yes, including end of input, is no.
+fn confirm(input: &mut dyn BufRead) -> io::Result<bool> {
+    print!("Change existing files as above? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Apply actions of the plan in order as a transaction. Files are backed up before touched and
+// paths created are recorded, to restore the system when any action fails. Commands are run
+// with the runner.
+pub fn execute(plan: &Plan, runner: &mut dyn CommandRunner) -> InstallerResult<()> {
+    let mut transaction = Transaction::new();
+
+    for action in plan.actions() {
+        println!("{}", action);
+        if let Err(e) = apply(action, runner, &mut transaction) {
+            println!("Failed: {}. Rolling back changes...", e);
+            let errors = transaction.rollback(runner);
+            if errors.is_empty() {
+                return Err(e);
+            }
+            return Err(format!(
+                "{}\nRollback failed. Fix them by hand:\n  {}",
+                e,
+                errors.join("\n  ")
+            )
+            .into());
+        }
+    }
+
+    if let Err(e) = transaction.commit() {
+        println!("warning: Can not remove backup: {}", e);
+    }
+    Ok(())
+}
+
+fn apply(
+    action: &Action,
+    runner: &mut dyn CommandRunner,
+    transaction: &mut Transaction,
+) -> InstallerResult<()> {
+    match action {
+        Action::CreateDir(path) => {
+            transaction.record_created_dir(path);
+            fs::create_dir_all(path)?;
+        }
+        Action::WriteFile {
+            path,
+            content,
+            executable,
+        } => {
+            transaction.backup_file(path)?;
+            fs::write(path, content)?;
+            if *executable {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        Action::RemoveFile(path) => {
+            transaction.backup_file(path)?;
+            match fs::remove_file(path) {
+                Ok(_) => (),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // moved aside, and deleted on commit
+        Action::RemoveDir(path) => transaction.move_aside(path)?,
+        Action::EditMimeApps { path, .. }
+        | Action::EditPlistKey { path, .. }
+        | Action::AddUrlType(path) => {
+            let original = plan::read_file(path)?;
+            let content = action.apply_to(original.clone())?;
+            if let Action::AddUrlType(_) = action {
+                if content == original {
+                    println!("org-protocol is already registered in CFBundleURLTypes.");
+                    return Ok(());
+                }
+                println!("Added org-protocol to CFBundleURLTypes.");
+            }
+            // file is left as is when the edit does not change anything
+            let content = match content {
+                Some(content) if Some(&content) != original.as_ref() => content,
+                _ => return Ok(()),
+            };
+
+            if let Some(parent) = path.parent() {
+                transaction.record_created_dir(parent);
+                fs::create_dir_all(parent)?;
+            }
+            transaction.backup_file(path)?;
+            fs::write(path, content)?;
+        }
+        Action::RunCommand {
+            program,
+            args,
+            outputs,
+            undo_args,
+        } => {
+            for output in outputs {
+                transaction.move_aside(output)?;
+            }
+            runner.run(program, args)?;
+            if let Some(undo_args) = undo_args {
+                transaction.record_command(program, undo_args);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use tempfile::TempDir;
+
+    use command::RecordingCommandRunner;
+
+    use super::*;
+
+    #[test]
+    fn roll_back_all_changes_on_failure() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let list = dir.path().join("config/mimeapps.list");
+        fs::create_dir_all(list.parent().unwrap()).unwrap();
+        fs::write(&list, "[Default Applications]\n").unwrap();
+        let bundle = dir.path().join("Client.app");
+        fs::create_dir_all(bundle.join("Contents")).unwrap();
+        let applications = dir.path().join("data/applications");
+        let plan = Plan::from(vec![
+            Action::CreateDir(applications.clone()),
+            Action::write_file(&applications.join("org-protocol.desktop"), b""),
+            Action::EditMimeApps {
+                path: list.clone(),
+                group: String::from(linux_installer::mimeapps::DEFAULT_APPLICATIONS),
+                mime_type: String::from("x-scheme-handler/org-protocol"),
+                desktop_ids: Some(vec![String::from("org-protocol.desktop")]),
+            },
+            Action::RemoveDir(bundle.clone()),
+            Action::run_command(Path::new("xdg-mime"), &[]),
+        ]);
+        let mut runner = RecordingCommandRunner::default();
+        runner.set_output(Path::new("xdg-mime"), None);
+
+        // do
+        let actual = execute(&plan, &mut runner);
+
+        // verify
+        assert_eq!(actual.unwrap_err().to_string(), "xdg-mime failed");
+        assert_eq!(
+            fs::read_to_string(&list).unwrap(),
+            "[Default Applications]\n"
+        );
+        assert!(!dir.path().join("data").exists());
+        assert!(bundle.join("Contents").is_dir());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn leave_file_as_is_if_edit_changes_nothing() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let existing = dir.path().join("existing.plist");
+        let original = r#"<plist version="1.0"><dict></dict></plist>"#;
+        fs::write(&existing, original).unwrap();
+        let missing = dir.path().join("Library/missing.plist");
+        let plan = Plan::from(vec![
+            Action::EditPlistKey {
+                path: existing.clone(),
+                key: String::from("LSHandlers"),
+                value: None,
+            },
+            Action::EditPlistKey {
+                path: missing.clone(),
+                key: String::from("LSHandlers"),
+                value: None,
+            },
+        ]);
+
+        // do
+        execute(&plan, &mut RecordingCommandRunner::default()).unwrap();
+
+        // verify
+        assert_eq!(fs::read_to_string(&existing).unwrap(), original);
+        assert!(!dir.path().join("Library").exists());
+    }
+
+    #[test]
+    fn unregister_bundle_on_failure() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("Client.app");
+        let plan = Plan::from(vec![
+            Action::CreateDir(bundle.clone()),
+            macos_installer::launch_services::register(&bundle),
+            Action::run_command(Path::new("false"), &[]),
+        ]);
+        let mut runner = RecordingCommandRunner::default();
+        runner.set_output(Path::new("false"), None);
+
+        // do
+        let actual = execute(&plan, &mut runner);
+
+        // verify
+        assert_eq!(actual.unwrap_err().to_string(), "false failed");
+        let bundle = bundle.to_string_lossy().to_string();
+        assert_eq!(
+            runner.commands()[2],
+            vec![macos_installer::launch_services::LSREGISTER, "-u", &bundle]
+        );
+        assert_eq!(runner.commands().len(), 3);
+        assert!(!dir.path().join("Client.app").exists());
+    }
+
+    #[test]
+    fn fail_dry_run_after_report_if_preflight_fails() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let plan = Plan::from(vec![
+            Action::write_file(&dir.path().join("org-protocol.desktop"), b""),
+            Action::run_command(&dir.path().join("missing-command"), &[]),
+        ]);
+
+        // do
+        let actual = apply_plan(&plan, true, false);
+
+        // verify
+        assert!(actual.is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn confirm_only_with_yes() {
+        // arrange
+        // do
+        // verify
+        assert!(confirm(&mut Cursor::new("y\n")).unwrap());
+        assert!(confirm(&mut Cursor::new("Yes\n")).unwrap());
+        assert!(!confirm(&mut Cursor::new("\n")).unwrap());
+        assert!(!confirm(&mut Cursor::new("no\n")).unwrap());
+        assert!(!confirm(&mut Cursor::new("")).unwrap());
+    }
 }
@@ -1,29 +1,52 @@
-use std::fs::remove_file;
-use std::fs::File;
+use std::fs;
 use std::io;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process;
+use std::path::{Path, PathBuf};
 
-use crate::config::LinuxConfig;
+use crate::config::{ConflictPolicy, LinuxConfig, MimeCacheUpdater};
+use crate::plan::{self, Action, Plan};
+use crate::stable_path;
 
 use super::InstallerResult;
 use super::RoamProtocolInstaller;
 
-const DESKTOP_FILE_CONTENT: &str = r#"
-[Desktop Entry]
-Name=Org-Protocol
-Exec=emacsclient %u
-Icon=emacs-icon
-Type=Application
-Terminal=false
-MimeType=x-scheme-handler/org-protocol
-"#;
+pub mod conflicts;
+pub mod desktop_entry;
+pub mod mimeapps;
+pub mod mimeinfo_cache;
+pub mod resolve;
+pub mod validator;
+pub mod xdg;
+
+use desktop_entry::{
+    quote_exec_argument, split_exec_program, DesktopEntry, CREATED_OVERRIDES_KEY,
+    EDITED_OVERRIDES_KEY, ORG_PROTOCOL_MIME_TYPE, PREVIOUS_HANDLER_KEY,
+};
+use mimeapps::MimeApps;
+use validator::Issue;
 
 pub fn new(config: LinuxConfig) -> Box<dyn RoamProtocolInstaller> {
     Box::new(LinuxRoamProtocolInstaller::new(config))
 }
 
+// Validate the desktop file given in config, or desktop entry to be installed
+pub fn validate(config: &LinuxConfig) -> InstallerResult<Vec<Issue>> {
+    match &config.validate_path {
+        Some(path) => Ok(validator::validate_content(&fs::read_to_string(path)?)),
+        None => Ok(validator::validate(&DesktopEntry::from(config))),
+    }
+}
+
+// Desktop file to write to override other one handling org-protocol
+struct Override {
+    path: PathBuf,
+    entry: DesktopEntry,
+    // true if new desktop file is written, false if the conflicting one is edited in place
+    created: bool,
+}
+
+// Desktop file to write, or to remove with None
+type DesktopFileChange = (PathBuf, Option<DesktopEntry>);
+
 struct LinuxRoamProtocolInstaller {
     config: LinuxConfig,
 }
@@ -36,46 +59,596 @@ impl LinuxRoamProtocolInstaller {
         self.config.get_desktop_file_path().unwrap_or_default()
     }
 
-    fn open_desktop_file(&self) -> InstallerResult<File> {
-        let desktop_file_path = self.get_desktop_file_path();
+    // Check permission before touching anything, because system wide directories are
+    // writable only by root in most distributions.
+    fn preflight(&self) -> InstallerResult<()> {
+        if !self.config.system {
+            return Ok(());
+        }
 
-        let f = File::create(&desktop_file_path)?;
-        Ok(f)
+        check_writable(self.config.desktop_entry_directory.as_path())?;
+        if let Some(parent) = self.config.mimeapps_list_path.parent() {
+            check_writable(parent)?;
+        }
+        Ok(())
     }
 
-    fn install_mime_for_xdg(&self) {
-        process::Command::new("xdg-mime")
-            .args(&[
-                "default",
-                "org-protocol.desktop",
-                "x-scheme-handler/org-protocol",
-            ])
-            .output()
-            .expect("Can not execute xdg-mime");
+    // read desktop file installed before, if exists
+    fn read_installed_entry(&self) -> InstallerResult<Option<DesktopEntry>> {
+        match fs::read_to_string(self.get_desktop_file_path()) {
+            Ok(content) => Ok(Some(DesktopEntry::parse(&content)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    // Get default applications of org-protocol before installation, with fallbacks after the
+    // first one. When the installer already registered own desktop file, they are the ones
+    // recorded at the first installation.
+    fn previous_handler(&self, list: &MimeApps) -> InstallerResult<Option<Vec<String>>> {
+        match list.get(mimeapps::DEFAULT_APPLICATIONS, ORG_PROTOCOL_MIME_TYPE) {
+            Some(ids) if ids.first() == Some(&self.config.desktop_file_name) => Ok(self
+                .read_installed_entry()?
+                .and_then(|entry| entry.get_list(PREVIOUS_HANDLER_KEY))),
+            Some(ids) if ids.is_empty() => Ok(None),
+            handler => Ok(handler),
+        }
+    }
+
+    fn install_mime_for_xdg(&self, list: &MimeApps) -> Vec<Action> {
+        let mut edited = list.clone();
+        edited.set_default_application(ORG_PROTOCOL_MIME_TYPE, &self.config.desktop_file_name);
+
+        Action::mimeapps_edits(
+            self.config.mimeapps_list_path.as_path(),
+            ORG_PROTOCOL_MIME_TYPE,
+            list,
+            &edited,
+        )
+    }
+
+    // Regenerate mimeinfo.cache, for desktop environments that do not watch the directory.
+    // Desktop files written or removed by the plan are given, because they are not changed
+    // yet while planning.
+    fn update_mime_cache(&self, changes: &[DesktopFileChange]) -> Vec<Action> {
+        let dir = self.config.desktop_entry_directory.as_path();
+        if !dir.is_dir() && changes.iter().all(|(_, entry)| entry.is_none()) {
+            return Vec::new();
+        }
+
+        match self.config.mime_cache_updater {
+            MimeCacheUpdater::Builtin => {
+                let mut entries = mimeinfo_cache::read_entries(dir);
+                for (path, entry) in changes {
+                    let id = match xdg::desktop_file_id(dir, path) {
+                        Some(id) => id,
+                        None => continue,
+                    };
+                    match entry {
+                        Some(entry) => entries.insert(id, entry.clone()),
+                        None => entries.remove(&id),
+                    };
+                }
+                let cache = mimeinfo_cache::build_from(&entries);
+
+                vec![Action::write_file(
+                    &mimeinfo_cache::path_in(dir),
+                    cache.as_bytes(),
+                )]
+            }
+            MimeCacheUpdater::External => vec![Action::run_command(
+                Path::new("update-desktop-database"),
+                &[dir.to_string_lossy().to_string()],
+            )],
+        }
+    }
+
+    // Report other desktop files handling org-protocol, and override them if requested.
+    // Overriding works only when desktop entry directory is preferred to the directory of
+    // the conflicting one.
+    fn handle_conflicts(&self) -> Vec<Override> {
+        let dirs = self.config.search_paths.applications_dirs();
+        let override_dir = self.config.desktop_entry_directory.as_path();
+        let override_index = dirs.iter().position(|dir| dir == override_dir);
+        let conflicts = conflicts::find_conflicts(&dirs, &self.config.desktop_file_name);
+        let mut overrides = Vec::new();
+
+        if conflicts.is_empty() {
+            return overrides;
+        }
+
+        println!("Found other desktop files handling org-protocol:");
+        for conflict in &conflicts {
+            println!(
+                "  {} ({})",
+                conflict.desktop_id,
+                conflict.path.to_string_lossy()
+            );
+
+            let conflict_index = dirs.iter().position(|dir| conflict.path.starts_with(dir));
+            let overridable = match (override_index, conflict_index) {
+                (Some(o), Some(c)) => o <= c,
+                _ => false,
+            };
+            if self.config.conflict_policy != ConflictPolicy::Report && !overridable {
+                println!(
+                    "    Can not override it from {}. Remove org-protocol from it by hand.",
+                    override_dir.to_string_lossy()
+                );
+                continue;
+            }
+
+            if let Some((path, entry)) =
+                conflicts::override_conflict(conflict, override_dir, &self.config.conflict_policy)
+            {
+                println!("    Override by {}", path.to_string_lossy());
+                overrides.push(Override {
+                    created: path != conflict.path,
+                    path,
+                    entry,
+                });
+            }
+        }
+
+        if self.config.conflict_policy == ConflictPolicy::Report {
+            println!("Run with --conflicts hide or --conflicts shadow to override them.");
+        }
+        overrides
+    }
+
+    // Record overrides in own desktop entry, with ones recorded at previous installation
+    fn record_overrides(
+        &self,
+        entry: &mut DesktopEntry,
+        installed: Option<&DesktopEntry>,
+        overrides: &[Override],
+    ) {
+        for (key, created) in &[(CREATED_OVERRIDES_KEY, true), (EDITED_OVERRIDES_KEY, false)] {
+            let mut paths = installed.and_then(|v| v.get_list(key)).unwrap_or_default();
+            for o in overrides.iter().filter(|o| o.created == *created) {
+                let path = o.path.to_string_lossy().to_string();
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+
+            if !paths.is_empty() {
+                let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+                entry.set_list(key, &paths);
+            }
+        }
+    }
+
+    // Remove overrides recorded at installation, and revert desktop files edited in place
+    fn remove_overrides(
+        &self,
+        installed: Option<&DesktopEntry>,
+    ) -> InstallerResult<Vec<DesktopFileChange>> {
+        let mut changes = Vec::new();
+        let recorded = |key| {
+            installed
+                .and_then(|v| v.get_list(key))
+                .unwrap_or_default()
+                .into_iter()
+                .map(PathBuf::from)
+        };
+
+        for path in recorded(CREATED_OVERRIDES_KEY) {
+            if path.exists() {
+                changes.push((path, None));
+            }
+        }
+        for path in recorded(EDITED_OVERRIDES_KEY) {
+            let entry = match fs::read_to_string(&path) {
+                Ok(content) => DesktopEntry::parse(&content)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(entry) = conflicts::revert_override(&entry) {
+                changes.push((path, Some(entry)));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    // Restore handler recorded at installation. The association is kept as is when the
+    // user already changed default application to other one.
+    fn uninstall_mime_for_xdg(
+        &self,
+        previous_handler: Option<Vec<String>>,
+    ) -> InstallerResult<Vec<Action>> {
+        let path = self.config.mimeapps_list_path.as_path();
+        let list = MimeApps::read(path)?;
+        let mut edited = list.clone();
+        let desktop_file_name = self.config.desktop_file_name.as_str();
+
+        if list.default_application(ORG_PROTOCOL_MIME_TYPE).as_deref() == Some(desktop_file_name) {
+            match previous_handler {
+                Some(handler) => {
+                    println!("Restore {} as handler of org-protocol", handler.join(";"));
+                    let ids = handler.iter().map(String::as_str).collect::<Vec<_>>();
+                    edited.set(mimeapps::DEFAULT_APPLICATIONS, ORG_PROTOCOL_MIME_TYPE, &ids)
+                }
+                None => {
+                    edited.remove(mimeapps::DEFAULT_APPLICATIONS, ORG_PROTOCOL_MIME_TYPE);
+                }
+            }
+        }
+        edited.remove_association(ORG_PROTOCOL_MIME_TYPE, desktop_file_name);
+
+        Ok(Action::mimeapps_edits(
+            path,
+            ORG_PROTOCOL_MIME_TYPE,
+            &list,
+            &edited,
+        ))
     }
 }
 
 impl RoamProtocolInstaller for LinuxRoamProtocolInstaller {
-    fn install(&mut self) -> InstallerResult<()> {
-        println!("Install desktop file...");
-        let mut f: File = self.open_desktop_file()?;
+    fn install(&mut self) -> InstallerResult<Plan> {
+        self.preflight()?;
+        let list = MimeApps::read(self.config.mimeapps_list_path.as_path())?;
+        let previous_handler = self.previous_handler(&list)?;
+        let overrides = self.handle_conflicts();
 
-        f.write_all(DESKTOP_FILE_CONTENT.as_bytes())?;
+        let mut entry = DesktopEntry::from(&self.config);
+        if let Some(handler) = &previous_handler {
+            let ids = handler.iter().map(String::as_str).collect::<Vec<_>>();
+            entry.set_list(PREVIOUS_HANDLER_KEY, &ids);
+        }
+        self.record_overrides(
+            &mut entry,
+            self.read_installed_entry()?.as_ref(),
+            &overrides,
+        );
+        let issues = validator::validate(&entry);
+        for issue in &issues {
+            println!("  {}", issue);
+        }
+        if validator::has_error(&issues) {
+            return Err("Generated desktop entry is not valid".into());
+        }
 
-        println!("Install xdg-mime to this environment...");
-        self.install_mime_for_xdg();
-        Ok(())
+        let mut changes = vec![(PathBuf::from(self.get_desktop_file_path()), Some(entry))];
+        changes.extend(overrides.into_iter().map(|o| (o.path, Some(o.entry))));
+
+        let mut plan = Plan::new();
+        plan.push(Action::CreateDir(
+            self.config.desktop_entry_directory.clone(),
+        ));
+        plan.extend(desktop_file_actions(&changes[..1]));
+        plan.extend(self.install_mime_for_xdg(&list));
+        plan.extend(desktop_file_actions(&changes[1..]));
+        plan.extend(self.update_mime_cache(&changes));
+
+        Ok(plan)
     }
 
-    fn uninstall(&mut self) -> InstallerResult<()> {
-        println!("Remove desktop file...");
+    fn uninstall(&mut self) -> InstallerResult<Plan> {
+        self.preflight()?;
+        let installed = self.read_installed_entry()?;
+        let previous_handler = installed
+            .as_ref()
+            .and_then(|entry| entry.get_list(PREVIOUS_HANDLER_KEY));
+
+        let mut changes = self.remove_overrides(installed.as_ref())?;
         let path = PathBuf::from(self.get_desktop_file_path());
-        let path = path.as_path();
+        if path.exists() {
+            changes.push((path, None));
+        }
 
-        match remove_file(&path) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
-            Err(e) => Err(Box::new(e)),
+        let mut plan = Plan::from(self.uninstall_mime_for_xdg(previous_handler)?);
+        plan.extend(desktop_file_actions(&changes));
+        plan.extend(self.update_mime_cache(&changes));
+
+        Ok(plan)
+    }
+
+    fn repair(&mut self) -> InstallerResult<Plan> {
+        let path = self.get_desktop_file_path();
+        let mut entry = self
+            .read_installed_entry()?
+            .ok_or_else(|| format!("{} is not installed", path))?;
+        let exec = entry
+            .get("Exec")
+            .ok_or_else(|| format!("{} does not have Exec", path))?;
+        let (program, rest) =
+            split_exec_program(&exec).ok_or_else(|| format!("Can not parse Exec of {}", path))?;
+
+        let home = dirs::home_dir();
+        let stable = match stable_path::repaired(Path::new(&program), home.as_deref())? {
+            Some(stable) => stable,
+            None => {
+                println!("{} does not contain version, nothing to repair", program);
+                return Ok(Plan::new());
+            }
+        };
+
+        println!("Rewrite {} to {} in {}", program, stable.display(), path);
+        let exec = format!("{}{}", quote_exec_argument(&stable.to_string_lossy()), rest);
+        entry.set("Exec", &exec);
+        Ok(Plan::from(vec![Action::write_file(
+            Path::new(&path),
+            entry.to_string().as_bytes(),
+        )]))
+    }
+}
+
+fn desktop_file_actions(changes: &[DesktopFileChange]) -> Vec<Action> {
+    changes
+        .iter()
+        .map(|(path, entry)| match entry {
+            Some(entry) => Action::write_file(path, entry.to_string().as_bytes()),
+            None => Action::RemoveFile(path.clone()),
+        })
+        .collect()
+}
+
+// Check the process can write into the directory, with hint to run with root permission
+fn check_writable(dir: &Path) -> InstallerResult<()> {
+    if plan::is_writable_dir(dir)? {
+        return Ok(());
+    }
+
+    Err(format!(
+        "Can not write into {}. Installation with --system needs root permission, run again with sudo",
+        dir.to_string_lossy()
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use tempfile::TempDir;
+
+    use crate::command::RecordingCommandRunner;
+    use crate::config::EmacsclientOptions;
+    use crate::execute;
+
+    use super::*;
+    use xdg::SearchPaths;
+
+    fn config_in(dir: &Path) -> LinuxConfig {
+        LinuxConfig {
+            desktop_entry_directory: dir.join("applications"),
+            desktop_file_name: String::from("org-protocol.desktop"),
+            entry_name: String::from("Org-Protocol"),
+            icon: String::from("emacs-icon"),
+            emacsclient: EmacsclientOptions::default(),
+            mimeapps_list_path: dir.join("mimeapps.list"),
+            system: false,
+            conflict_policy: ConflictPolicy::Report,
+            mime_cache_updater: MimeCacheUpdater::Builtin,
+            validate_path: None,
+            search_paths: SearchPaths {
+                desktops: vec![],
+                config_home: Some(dir.to_path_buf()),
+                config_dirs: vec![],
+                data_home: Some(dir.to_path_buf()),
+                data_dirs: vec![dir.join("system")],
+            },
         }
     }
+
+    fn apply(plan: InstallerResult<Plan>) {
+        execute(&plan.unwrap(), &mut RecordingCommandRunner::default()).unwrap();
+    }
+
+    #[test]
+    fn repair_version_pinned_emacsclient() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let prefix = dir.path().join("homebrew");
+        let pinned = prefix.join("Cellar/emacs/29.1/bin/emacsclient");
+        fs::create_dir_all(pinned.parent().unwrap()).unwrap();
+        fs::write(&pinned, "").unwrap();
+        fs::create_dir_all(prefix.join("opt")).unwrap();
+        std::os::unix::fs::symlink(prefix.join("Cellar/emacs/29.1"), prefix.join("opt/emacs"))
+            .unwrap();
+        let mut config = config_in(dir.path());
+        config.emacsclient.path = pinned;
+        config.emacsclient.socket_name = Some(String::from("work"));
+        let desktop_file = PathBuf::from(config.get_desktop_file_path().unwrap());
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+        apply(installer.install());
+
+        // do
+        apply(installer.repair());
+
+        // verify
+        let entry = DesktopEntry::parse(&fs::read_to_string(desktop_file).unwrap()).unwrap();
+        assert_eq!(
+            entry.get("Exec"),
+            Some(format!(
+                "{} --socket-name=work %u",
+                quote_exec_argument(&prefix.join("opt/emacs/bin/emacsclient").to_string_lossy())
+            ))
+        );
+    }
+
+    #[test]
+    fn restore_previous_handler_on_uninstall() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let config = config_in(dir.path());
+        fs::create_dir_all(&config.desktop_entry_directory).unwrap();
+        fs::write(
+            &config.mimeapps_list_path,
+            "[Default Applications]\nx-scheme-handler/org-protocol=emacsclient.desktop;firefox.desktop;\n",
+        )
+        .unwrap();
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+
+        // do
+        apply(installer.install());
+        apply(installer.install());
+        let installed = MimeApps::read(&installer.config.mimeapps_list_path).unwrap();
+        apply(installer.uninstall());
+
+        // verify
+        let list = MimeApps::read(&installer.config.mimeapps_list_path).unwrap();
+        assert_eq!(
+            installed.default_application(ORG_PROTOCOL_MIME_TYPE),
+            Some(String::from("org-protocol.desktop"))
+        );
+        assert_eq!(
+            list.get(mimeapps::DEFAULT_APPLICATIONS, ORG_PROTOCOL_MIME_TYPE),
+            Some(vec![
+                String::from("emacsclient.desktop"),
+                String::from("firefox.desktop")
+            ])
+        );
+        assert_eq!(
+            list.get(mimeapps::ADDED_ASSOCIATIONS, ORG_PROTOCOL_MIME_TYPE),
+            None
+        );
+        assert!(!Path::new(&installer.get_desktop_file_path()).exists());
+    }
+
+    #[test]
+    fn keep_handler_changed_after_installation() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let config = config_in(dir.path());
+        fs::create_dir_all(&config.desktop_entry_directory).unwrap();
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+        apply(installer.install());
+        let mut list = MimeApps::read(&installer.config.mimeapps_list_path).unwrap();
+        list.set(
+            mimeapps::DEFAULT_APPLICATIONS,
+            ORG_PROTOCOL_MIME_TYPE,
+            &["other.desktop"],
+        );
+        list.write(&installer.config.mimeapps_list_path).unwrap();
+
+        // do
+        apply(installer.uninstall());
+
+        // verify
+        let list = MimeApps::read(&installer.config.mimeapps_list_path).unwrap();
+        assert_eq!(
+            list.default_application(ORG_PROTOCOL_MIME_TYPE),
+            Some(String::from("other.desktop"))
+        );
+    }
+
+    #[test]
+    fn shadow_conflicting_handler_on_install() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut config = config_in(dir.path());
+        config.conflict_policy = ConflictPolicy::Shadow;
+        let system = dir.path().join("system/applications");
+        fs::create_dir_all(&system).unwrap();
+        fs::write(
+            system.join("emacsclient.desktop"),
+            "[Desktop Entry]\nMimeType=text/plain;x-scheme-handler/org-protocol;\n",
+        )
+        .unwrap();
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+
+        // do
+        apply(installer.install());
+
+        // verify
+        assert_eq!(
+            fs::read_to_string(dir.path().join("applications/emacsclient.desktop")).unwrap(),
+            "[Desktop Entry]\nMimeType=text/plain;\n"
+        );
+    }
+
+    #[test]
+    fn remove_overrides_on_uninstall() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut config = config_in(dir.path());
+        config.conflict_policy = ConflictPolicy::Hide;
+        let system = dir.path().join("system/applications");
+        fs::create_dir_all(&system).unwrap();
+        let claim = "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n";
+        fs::write(system.join("emacsclient.desktop"), claim).unwrap();
+        fs::create_dir_all(&config.desktop_entry_directory).unwrap();
+        let own = config.desktop_entry_directory.join("my-emacs.desktop");
+        fs::write(&own, claim).unwrap();
+        fs::write(
+            &config.mimeapps_list_path,
+            "[Default Applications]\nx-scheme-handler/org-protocol=emacsclient.desktop;\n",
+        )
+        .unwrap();
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+        let hidden = dir.path().join("applications/emacsclient.desktop");
+
+        // do
+        apply(installer.install());
+        let hidden_after_install = fs::read_to_string(&hidden).unwrap();
+        apply(installer.install());
+        apply(installer.uninstall());
+
+        // verify
+        assert_eq!(hidden_after_install, "[Desktop Entry]\nHidden=true\n");
+        assert!(!hidden.exists());
+        assert_eq!(fs::read_to_string(&own).unwrap(), claim);
+        let list = MimeApps::read(&installer.config.mimeapps_list_path).unwrap();
+        assert_eq!(
+            list.default_application(ORG_PROTOCOL_MIME_TYPE),
+            Some(String::from("emacsclient.desktop"))
+        );
+    }
+
+    #[test]
+    fn restore_override_edited_in_place_on_uninstall() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut config = config_in(dir.path());
+        config.conflict_policy = ConflictPolicy::Hide;
+        fs::create_dir_all(&config.desktop_entry_directory).unwrap();
+        let edited = config.desktop_entry_directory.join("emacsclient.desktop");
+        let original = "[Desktop Entry]\nHidden=false\nMimeType=x-scheme-handler/org-protocol;\n";
+        fs::write(&edited, original).unwrap();
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+
+        // do
+        apply(installer.install());
+        let edited_after_install = fs::read_to_string(&edited).unwrap();
+        apply(installer.uninstall());
+
+        // verify
+        assert!(edited_after_install.contains("Hidden=true\n"));
+        assert_eq!(fs::read_to_string(&edited).unwrap(), original);
+    }
+
+    #[test]
+    fn build_mime_cache_with_overrides() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut config = config_in(dir.path());
+        config.conflict_policy = ConflictPolicy::Hide;
+        fs::create_dir_all(&config.desktop_entry_directory).unwrap();
+        fs::write(
+            config.desktop_entry_directory.join("my-emacs.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        )
+        .unwrap();
+        let cache = mimeinfo_cache::path_in(&config.desktop_entry_directory);
+        let mut installer = LinuxRoamProtocolInstaller::new(config);
+
+        // do
+        apply(installer.install());
+        let installed = fs::read_to_string(&cache).unwrap();
+        apply(installer.uninstall());
+
+        // verify
+        assert_eq!(
+            installed,
+            "[MIME Cache]\nx-scheme-handler/org-protocol=org-protocol.desktop;\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&cache).unwrap(),
+            "[MIME Cache]\nx-scheme-handler/org-protocol=my-emacs.desktop;\n"
+        );
+    }
 }
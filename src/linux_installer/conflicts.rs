@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ConflictPolicy;
+
+use super::desktop_entry::{
+    DesktopEntry, DESKTOP_ENTRY_GROUP, ORG_PROTOCOL_MIME_TYPE, ORIGINAL_VALUE_KEY,
+    OVERRIDDEN_KEY_KEY,
+};
+use super::xdg;
+
+// Desktop file other than ours that claims org-protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub desktop_id: String,
+    pub path: PathBuf,
+    entry: DesktopEntry,
+}
+
+// Find visible desktop files that have org-protocol in MimeType. Desktop files shadowed by
+// one with the same ID in more preferred directory are ignored.
+pub fn find_conflicts(applications_dirs: &[PathBuf], own_desktop_id: &str) -> Vec<Conflict> {
+    let mut seen = HashSet::new();
+    let mut conflicts = Vec::new();
+
+    for dir in applications_dirs {
+        for (id, path) in xdg::desktop_files_in(dir) {
+            if !seen.insert(id.clone()) || id == own_desktop_id {
+                continue;
+            }
+
+            let entry = match fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| DesktopEntry::parse(&content).ok())
+            {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let hidden = entry.get("Hidden").is_some_and(|v| v == "true");
+            let claimed = entry
+                .get_list("MimeType")
+                .is_some_and(|types| types.iter().any(|v| v == ORG_PROTOCOL_MIME_TYPE));
+
+            if claimed && !hidden {
+                conflicts.push(Conflict {
+                    desktop_id: id,
+                    path,
+                    entry,
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+// Disable the conflict with a desktop file in the override directory, that has the same ID
+// and so shadows the original. Return the path to write and the entry, or None if nothing
+// to do.
+pub fn override_conflict(
+    conflict: &Conflict,
+    override_dir: &Path,
+    policy: &ConflictPolicy,
+) -> Option<(PathBuf, DesktopEntry)> {
+    let in_place = conflict.path.starts_with(override_dir);
+    let mut entry = match policy {
+        ConflictPolicy::Report => return None,
+        ConflictPolicy::Hide if !in_place => DesktopEntry::new(),
+        _ => conflict.entry.clone(),
+    };
+
+    match policy {
+        ConflictPolicy::Report => (),
+        ConflictPolicy::Hide => {
+            if in_place {
+                record_original(&mut entry, "Hidden");
+            }
+            entry.set("Hidden", "true");
+        }
+        ConflictPolicy::Shadow => {
+            if in_place {
+                record_original(&mut entry, "MimeType");
+            }
+            let types = entry.get_list("MimeType").unwrap_or_default();
+            let types = types
+                .iter()
+                .map(String::as_str)
+                .filter(|v| *v != ORG_PROTOCOL_MIME_TYPE)
+                .collect::<Vec<_>>();
+
+            if types.is_empty() {
+                entry.remove("MimeType");
+            } else {
+                entry.set_list("MimeType", &types);
+            }
+        }
+    }
+
+    // Desktop file in the override directory is edited in place. Otherwise write new one;
+    // desktop file ID `foo-bar.desktop` may come from `foo/bar.desktop`, but writing it flat
+    // into the override directory is enough to shadow it.
+    let path = if in_place {
+        conflict.path.clone()
+    } else {
+        override_dir.join(&conflict.desktop_id)
+    };
+
+    Some((path, entry))
+}
+
+// Record the key to change in the desktop file edited in place, with its value as written.
+// The first record is kept when the file is overridden again.
+fn record_original(entry: &mut DesktopEntry, key: &str) {
+    let group = entry.group_mut(DESKTOP_ENTRY_GROUP);
+    if group.get_raw(OVERRIDDEN_KEY_KEY, None).is_some() {
+        return;
+    }
+
+    if let Some(value) = group.get_raw(key, None).map(String::from) {
+        group.set_raw(ORIGINAL_VALUE_KEY, None, &value);
+    }
+    group.set_raw(OVERRIDDEN_KEY_KEY, None, key);
+}
+
+// Revert the override written into the conflicting desktop file in place, to the value
+// recorded before it. Return None if nothing is recorded.
+pub fn revert_override(entry: &DesktopEntry) -> Option<DesktopEntry> {
+    let mut entry = entry.clone();
+    let group = entry.group_mut(DESKTOP_ENTRY_GROUP);
+    let key = group.get_raw(OVERRIDDEN_KEY_KEY, None).map(String::from)?;
+
+    match group.get_raw(ORIGINAL_VALUE_KEY, None).map(String::from) {
+        Some(value) => group.set_raw(&key, None, &value),
+        None => group.remove(&key),
+    }
+    group.remove(OVERRIDDEN_KEY_KEY);
+    group.remove(ORIGINAL_VALUE_KEY);
+    Some(entry)
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn write(path: PathBuf, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn find_visible_desktop_files_claiming_org_protocol() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user");
+        let system = dir.path().join("system");
+        let claim = "[Desktop Entry]\nExec=emacsclient %u\nMimeType=text/plain;x-scheme-handler/org-protocol;\n";
+        write(user.join("org-protocol.desktop"), claim);
+        write(
+            user.join("hidden.desktop"),
+            "[Desktop Entry]\nHidden=true\n",
+        );
+        write(system.join("hidden.desktop"), claim);
+        write(system.join("emacs/client.desktop"), claim);
+        write(
+            system.join("other.desktop"),
+            "[Desktop Entry]\nMimeType=text/plain;\n",
+        );
+
+        // do
+        let actual = find_conflicts(&[user, system.clone()], "org-protocol.desktop");
+
+        // verify
+        assert_eq!(
+            actual
+                .iter()
+                .map(|v| (v.desktop_id.as_str(), v.path.clone()))
+                .collect::<Vec<_>>(),
+            vec![("emacs-client.desktop", system.join("emacs/client.desktop"))]
+        );
+    }
+
+    #[test]
+    fn shadow_conflict_without_org_protocol() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user");
+        let system = dir.path().join("system");
+        write(
+            system.join("emacsclient.desktop"),
+            "[Desktop Entry]\nName=Emacs (Client)\nMimeType=text/plain;x-scheme-handler/org-protocol;\n",
+        );
+        let conflicts = find_conflicts(&[user.clone(), system], "org-protocol.desktop");
+
+        // do
+        let (path, entry) =
+            override_conflict(&conflicts[0], &user, &ConflictPolicy::Shadow).unwrap();
+
+        // verify
+        assert_eq!(path, user.join("emacsclient.desktop"));
+        assert_eq!(
+            entry.to_string(),
+            "[Desktop Entry]\nName=Emacs (Client)\nMimeType=text/plain;\n"
+        );
+    }
+
+    #[test]
+    fn revert_override_edited_in_place_to_original() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user");
+        let original =
+            "[Desktop Entry]\nHidden=false\nMimeType=text/plain;x-scheme-handler/org-protocol;\n";
+        write(user.join("emacsclient.desktop"), original);
+        let conflicts = find_conflicts(std::slice::from_ref(&user), "org-protocol.desktop");
+
+        for policy in &[ConflictPolicy::Hide, ConflictPolicy::Shadow] {
+            // do
+            let (path, entry) = override_conflict(&conflicts[0], &user, policy).unwrap();
+            let reverted = revert_override(&entry).unwrap();
+
+            // verify
+            assert_eq!(path, user.join("emacsclient.desktop"));
+            assert_ne!(entry.to_string(), original);
+            assert_eq!(reverted.to_string(), original);
+        }
+        assert_eq!(
+            revert_override(&DesktopEntry::parse("[Desktop Entry]\nHidden=true\n").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn hide_conflict_with_user_override() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user");
+        let system = dir.path().join("system");
+        write(
+            system.join("emacsclient.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        let conflicts = find_conflicts(&[user.clone(), system.clone()], "org-protocol.desktop");
+
+        // do
+        let (path, entry) = override_conflict(&conflicts[0], &user, &ConflictPolicy::Hide).unwrap();
+        write(path, &entry.to_string());
+
+        // verify
+        assert_eq!(
+            fs::read_to_string(user.join("emacsclient.desktop")).unwrap(),
+            "[Desktop Entry]\nHidden=true\n"
+        );
+        assert_eq!(
+            find_conflicts(&[user, system], "org-protocol.desktop"),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,731 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::config::LinuxConfig;
+
+pub const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+pub const ORG_PROTOCOL_MIME_TYPE: &str = "x-scheme-handler/org-protocol";
+
+// key to record the list of default applications of org-protocol before installation
+pub const PREVIOUS_HANDLER_KEY: &str = "X-OrgRoamProtocol-PreviousHandler";
+
+// keys to record desktop files written to override other handlers of org-protocol. Created
+// ones are removed, and ones edited in place are reverted on uninstall.
+pub const CREATED_OVERRIDES_KEY: &str = "X-OrgRoamProtocol-CreatedOverrides";
+pub const EDITED_OVERRIDES_KEY: &str = "X-OrgRoamProtocol-EditedOverrides";
+
+// keys written into desktop file edited in place to override it, to record the key changed
+// and its value before the change. The value key is absent if the key did not exist.
+pub const OVERRIDDEN_KEY_KEY: &str = "X-OrgRoamProtocol-OverriddenKey";
+pub const ORIGINAL_VALUE_KEY: &str = "X-OrgRoamProtocol-OriginalValue";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    // comment or blank line. Kept as is to write back parsed file.
+    Comment(String),
+    Entry {
+        key: String,
+        locale: Option<String>,
+        // value is kept in escaped form
+        value: String,
+    },
+}
+
+// A group, such as `[Desktop Entry]`, in desktop entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntryGroup {
+    name: String,
+    lines: Vec<Line>,
+}
+
+impl DesktopEntryGroup {
+    pub fn new(name: &str) -> Self {
+        DesktopEntryGroup {
+            name: String::from(name),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // get raw (escaped) value of the key
+    pub fn get_raw(&self, key: &str, locale: Option<&str>) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Entry {
+                key: k,
+                locale: l,
+                value,
+            } if k == key && l.as_deref() == locale => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    // set raw (escaped) value of the key. Replace value if the key already exists.
+    pub fn set_raw(&mut self, key: &str, locale: Option<&str>, value: &str) {
+        for line in self.lines.iter_mut() {
+            if let Line::Entry {
+                key: k,
+                locale: l,
+                value: v,
+            } = line
+            {
+                if k == key && l.as_deref() == locale {
+                    *v = String::from(value);
+                    return;
+                }
+            }
+        }
+
+        self.lines.push(Line::Entry {
+            key: String::from(key),
+            locale: locale.map(String::from),
+            value: String::from(value),
+        });
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.get_raw(key, None).map(unescape_value)
+    }
+
+    pub fn get_localized(&self, key: &str, locale: &str) -> Option<String> {
+        self.get_raw(key, Some(locale)).map(unescape_value)
+    }
+
+    pub fn get_list(&self, key: &str) -> Option<Vec<String>> {
+        self.get_raw(key, None).map(split_list)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.set_raw(key, None, &escape_value(value))
+    }
+
+    pub fn set_localized(&mut self, key: &str, locale: &str, value: &str) {
+        self.set_raw(key, Some(locale), &escape_value(value))
+    }
+
+    pub fn set_list(&mut self, key: &str, values: &[&str]) {
+        self.set_raw(key, None, &join_list(values))
+    }
+
+    // remove the key and all localized variants of it
+    pub fn remove(&mut self, key: &str) {
+        self.lines.retain(|line| match line {
+            Line::Entry { key: k, .. } => k != key,
+            Line::Comment(_) => true,
+        })
+    }
+
+    // all keys with locale in this group in order of appearance
+    pub fn keys(&self) -> Vec<(&str, Option<&str>)> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry { key, locale, .. } => Some((key.as_str(), locale.as_deref())),
+                Line::Comment(_) => None,
+            })
+            .collect()
+    }
+}
+
+// Model of desktop entry file defined in Desktop Entry Specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    // comments before first group
+    header: Vec<String>,
+    groups: Vec<DesktopEntryGroup>,
+}
+
+impl Default for DesktopEntry {
+    fn default() -> Self {
+        DesktopEntry::new()
+    }
+}
+
+impl DesktopEntry {
+    pub fn new() -> Self {
+        DesktopEntry {
+            header: Vec::new(),
+            groups: vec![DesktopEntryGroup::new(DESKTOP_ENTRY_GROUP)],
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<DesktopEntry, ParseError> {
+        let mut header = Vec::new();
+        let mut groups: Vec<DesktopEntryGroup> = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                match groups.last_mut() {
+                    Some(group) => group.lines.push(Line::Comment(String::from(line))),
+                    None => header.push(String::from(line)),
+                }
+                continue;
+            }
+
+            if trimmed.starts_with('[') {
+                let name = trimmed
+                    .strip_prefix('[')
+                    .and_then(|v| v.strip_suffix(']'))
+                    .filter(|v| !v.is_empty() && !v.contains(['[', ']']))
+                    .ok_or_else(|| ParseError::new(index + 1, "invalid group header"))?;
+
+                if groups.iter().any(|g| g.name == name) {
+                    return Err(ParseError::new(
+                        index + 1,
+                        &format!("duplicated group: {}", name),
+                    ));
+                }
+                groups.push(DesktopEntryGroup::new(name));
+                continue;
+            }
+
+            let group = groups
+                .last_mut()
+                .ok_or_else(|| ParseError::new(index + 1, "entry found before any group"))?;
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| ParseError::new(index + 1, "line is not key=value"))?;
+            let (key, locale) =
+                parse_key(key.trim()).ok_or_else(|| ParseError::new(index + 1, "invalid key"))?;
+
+            group.lines.push(Line::Entry {
+                key: String::from(key),
+                locale: locale.map(String::from),
+                value: String::from(value.trim_start()),
+            });
+        }
+
+        Ok(DesktopEntry { header, groups })
+    }
+
+    pub fn group(&self, name: &str) -> Option<&DesktopEntryGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    // get the group, or create new group if it does not exist
+    pub fn group_mut(&mut self, name: &str) -> &mut DesktopEntryGroup {
+        if let Some(index) = self.groups.iter().position(|g| g.name == name) {
+            &mut self.groups[index]
+        } else {
+            self.groups.push(DesktopEntryGroup::new(name));
+            self.groups.last_mut().unwrap()
+        }
+    }
+
+    pub fn groups(&self) -> &[DesktopEntryGroup] {
+        &self.groups
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.group(DESKTOP_ENTRY_GROUP).and_then(|g| g.get(key))
+    }
+
+    pub fn get_localized(&self, key: &str, locale: &str) -> Option<String> {
+        self.group(DESKTOP_ENTRY_GROUP)
+            .and_then(|g| g.get_localized(key, locale))
+    }
+
+    pub fn get_list(&self, key: &str) -> Option<Vec<String>> {
+        self.group(DESKTOP_ENTRY_GROUP)
+            .and_then(|g| g.get_list(key))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.group_mut(DESKTOP_ENTRY_GROUP).set(key, value)
+    }
+
+    pub fn set_localized(&mut self, key: &str, locale: &str, value: &str) {
+        self.group_mut(DESKTOP_ENTRY_GROUP)
+            .set_localized(key, locale, value)
+    }
+
+    pub fn set_list(&mut self, key: &str, values: &[&str]) {
+        self.group_mut(DESKTOP_ENTRY_GROUP).set_list(key, values)
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.group_mut(DESKTOP_ENTRY_GROUP).remove(key)
+    }
+}
+
+impl From<&LinuxConfig> for DesktopEntry {
+    fn from(config: &LinuxConfig) -> Self {
+        let mut entry = DesktopEntry::new();
+        entry.set("Name", &config.entry_name);
+        entry.set("Exec", &exec_command_line(config));
+        entry.set("Icon", &config.icon);
+        entry.set("Type", "Application");
+        entry.set("Terminal", "false");
+        entry.set_list("MimeType", &[ORG_PROTOCOL_MIME_TYPE]);
+        entry
+    }
+}
+
+impl FromStr for DesktopEntry {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DesktopEntry::parse(s)
+    }
+}
+
+impl Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.header {
+            writeln!(f, "{}", line)?;
+        }
+
+        for (index, group) in self.groups.iter().enumerate() {
+            // separate groups by blank line when the parsed file did not have one
+            let previous_is_blank = index > 0
+                && matches!(self.groups[index - 1].lines.last(), Some(Line::Comment(c)) if c.trim().is_empty());
+            if index > 0 && !previous_is_blank {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "[{}]", group.name)?;
+            for line in &group.lines {
+                match line {
+                    Line::Comment(c) => writeln!(f, "{}", c)?,
+                    Line::Entry {
+                        key,
+                        locale: Some(locale),
+                        value,
+                    } => writeln!(f, "{}[{}]={}", key, locale, value)?,
+                    Line::Entry {
+                        key,
+                        locale: None,
+                        value,
+                    } => writeln!(f, "{}={}", key, value)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: &str) -> Self {
+        ParseError {
+            line,
+            message: String::from(message),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "desktop entry line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+// Exec value to invoke emacsclient with the URL
+fn exec_command_line(config: &LinuxConfig) -> String {
+    let path = config.emacsclient.path.to_string_lossy();
+    let mut args = vec![quote_exec_argument(&path)];
+
+    args.extend(
+        config
+            .emacsclient
+            .arguments()
+            .iter()
+            .map(|v| quote_exec_argument(v)),
+    );
+    args.push(String::from("%u"));
+
+    args.join(" ")
+}
+
+// quote an argument of Exec key according to the specification. Returned value is not
+// escaped as string yet.
+pub fn quote_exec_argument(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`',
+    ];
+
+    // literal percent sign must be doubled, not to be treated as field code
+    let arg = arg.replace('%', "%%");
+
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg;
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+// Split the program from arguments of Exec, that is already unescaped as string. Return the
+// program unquoted, and the rest of Exec after it.
+pub fn split_exec_program(exec: &str) -> Option<(String, &str)> {
+    let exec = exec.trim_start();
+
+    if let Some(quoted) = exec.strip_prefix('"') {
+        let mut program = String::new();
+        let mut chars = quoted.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((program.replace("%%", "%"), &quoted[i + 1..])),
+                '\\' => program.push(chars.next()?.1),
+                c => program.push(c),
+            }
+        }
+        return None;
+    }
+
+    let end = exec.find([' ', '\t', '\n']).unwrap_or(exec.len());
+    if end == 0 {
+        return None;
+    }
+    Some((exec[..end].replace("%%", "%"), &exec[end..]))
+}
+
+// Validate the name as desktop file ID. Desktop file ID must end with `.desktop`, and its
+// basename should follow D-Bus well-known name, such as `org.example.OrgProtocol.desktop`.
+pub fn validate_desktop_file_id(name: &str) -> Result<(), &'static str> {
+    let basename = name
+        .strip_suffix(".desktop")
+        .ok_or("Desktop file name must end with .desktop")?;
+
+    if basename.is_empty() || name.len() > 255 {
+        return Err("Desktop file name must be between 1 and 255 characters before .desktop");
+    }
+
+    let valid_element = |element: &str| {
+        !element.is_empty()
+            && !element.starts_with(|c: char| c.is_ascii_digit())
+            && element
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    if !basename.split('.').all(valid_element) {
+        return Err("Desktop file name must be reverse-DNS style, such as org.example.OrgProtocol.desktop. Each element must contain only [A-Za-z0-9_-] and must not start with a digit");
+    }
+
+    Ok(())
+}
+
+// split `Name[ja]` into `Name` and `ja`
+fn parse_key(key: &str) -> Option<(&str, Option<&str>)> {
+    let (name, locale) = match key.find('[') {
+        Some(index) => {
+            let locale = key[index + 1..].strip_suffix(']')?;
+            if locale.is_empty() {
+                return None;
+            }
+            (&key[..index], Some(locale))
+        }
+        None => (key, None),
+    };
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+
+    Some((name, locale))
+}
+
+// escape value of string type
+pub fn escape_value(value: &str) -> String {
+    let mut ret = String::new();
+
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            '\r' => ret.push_str("\\r"),
+            // leading space is trimmed by reader, so it must be escaped
+            ' ' if index == 0 => ret.push_str("\\s"),
+            _ => ret.push(c),
+        }
+    }
+
+    ret
+}
+
+pub fn unescape_value(value: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => ret.push(' '),
+            Some('n') => ret.push('\n'),
+            Some('t') => ret.push('\t'),
+            Some('r') => ret.push('\r'),
+            Some('\\') => ret.push('\\'),
+            Some(c) => {
+                ret.push('\\');
+                ret.push(c);
+            }
+            None => ret.push('\\'),
+        }
+    }
+
+    ret
+}
+
+fn join_list(values: &[&str]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{};", escape_value(v).replace(';', "\\;")))
+        .collect()
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(c) => {
+                    current.push('\\');
+                    current.push(c);
+                }
+                None => current.push('\\'),
+            },
+            ';' => {
+                ret.push(unescape_value(&current));
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        ret.push(unescape_value(&current));
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::config::{ConflictPolicy, EmacsclientOptions, MimeCacheUpdater};
+    use crate::linux_installer::xdg::SearchPaths;
+
+    use super::*;
+
+    #[test]
+    fn serialize_entry_built_from_config() {
+        // arrange
+        let config = LinuxConfig {
+            desktop_entry_directory: PathBuf::from("directory"),
+            desktop_file_name: String::from("org-protocol.desktop"),
+            entry_name: String::from("Org-Protocol"),
+            icon: String::from("emacs-icon"),
+            emacsclient: EmacsclientOptions::default(),
+            mimeapps_list_path: PathBuf::from("mimeapps.list"),
+            system: false,
+            conflict_policy: ConflictPolicy::Report,
+            mime_cache_updater: MimeCacheUpdater::Builtin,
+            validate_path: None,
+            search_paths: SearchPaths::from_env(),
+        };
+
+        // do
+        let actual = DesktopEntry::from(&config).to_string();
+
+        // verify
+        assert_eq!(
+            actual,
+            r#"[Desktop Entry]
+Name=Org-Protocol
+Exec=emacsclient %u
+Icon=emacs-icon
+Type=Application
+Terminal=false
+MimeType=x-scheme-handler/org-protocol;
+"#
+        )
+    }
+
+    #[test]
+    fn quote_emacsclient_invocation_in_exec() {
+        // arrange
+        let config = LinuxConfig {
+            desktop_entry_directory: PathBuf::from("directory"),
+            desktop_file_name: String::from("org-protocol.desktop"),
+            entry_name: String::from("Org-Protocol"),
+            icon: String::from("emacs-icon"),
+            emacsclient: EmacsclientOptions {
+                path: PathBuf::from("/home/user/.nix profile/bin/emacsclient"),
+                socket_name: Some(String::from("work")),
+                server_file: None,
+                create_frame: true,
+                no_wait: false,
+                alternate_editor: Some(String::from("")),
+            },
+            mimeapps_list_path: PathBuf::from("mimeapps.list"),
+            system: false,
+            conflict_policy: ConflictPolicy::Report,
+            mime_cache_updater: MimeCacheUpdater::Builtin,
+            validate_path: None,
+            search_paths: SearchPaths::from_env(),
+        };
+
+        // do
+        let entry = DesktopEntry::from(&config);
+
+        // verify
+        assert_eq!(
+            entry.get("Exec"),
+            Some(String::from(
+                r#""/home/user/.nix profile/bin/emacsclient" --socket-name=work --create-frame --alternate-editor= %u"#
+            ))
+        )
+    }
+
+    #[test]
+    fn quote_reserved_characters_in_exec_argument() {
+        // arrange
+        // do
+        let actual = quote_exec_argument(r#"a "b" $c\d 100%"#);
+
+        // verify
+        assert_eq!(actual, r#""a \"b\" \$c\\d 100%%""#);
+        assert_eq!(quote_exec_argument(""), r#""""#);
+    }
+
+    #[test]
+    fn accept_valid_desktop_file_id() {
+        // arrange
+        // do
+        // verify
+        assert_eq!(validate_desktop_file_id("org-protocol.desktop"), Ok(()));
+        assert_eq!(
+            validate_desktop_file_id("org.example.Org_Protocol.desktop"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reject_invalid_desktop_file_id() {
+        // arrange
+        // do
+        // verify
+        assert_eq!(
+            validate_desktop_file_id("org-protocol"),
+            Err("Desktop file name must end with .desktop")
+        );
+        assert!(validate_desktop_file_id("org..protocol.desktop").is_err());
+        assert!(validate_desktop_file_id("org.1protocol.desktop").is_err());
+        assert!(validate_desktop_file_id("sub/org-protocol.desktop").is_err());
+        assert!(validate_desktop_file_id(".desktop").is_err());
+    }
+
+    #[test]
+    fn parse_localized_keys_and_groups() {
+        // arrange
+        let content = r#"# comment
+[Desktop Entry]
+Name=Org-Protocol
+Name[ja]=オルグ
+MimeType=a/b;c\;d;
+
+[Desktop Action new]
+Exec=emacsclient -c
+"#;
+
+        // do
+        let actual = DesktopEntry::parse(content).unwrap();
+
+        // verify
+        assert_eq!(actual.get("Name"), Some(String::from("Org-Protocol")));
+        assert_eq!(
+            actual.get_localized("Name", "ja"),
+            Some(String::from("オルグ"))
+        );
+        assert_eq!(
+            actual.get_list("MimeType"),
+            Some(vec![String::from("a/b"), String::from("c;d")])
+        );
+        assert_eq!(
+            actual.group("Desktop Action new").unwrap().get("Exec"),
+            Some(String::from("emacsclient -c"))
+        );
+        assert_eq!(actual.to_string(), content);
+    }
+
+    #[test]
+    fn escape_special_characters() {
+        // arrange
+        let mut entry = DesktopEntry::new();
+
+        // do
+        entry.set("Comment", " a\\b\nc");
+
+        // verify
+        assert_eq!(
+            entry.to_string(),
+            "[Desktop Entry]\nComment=\\sa\\\\b\\nc\n"
+        );
+        assert_eq!(entry.get("Comment"), Some(String::from(" a\\b\nc")));
+    }
+
+    #[test]
+    fn get_error_for_invalid_line() {
+        // arrange
+        let content = "[Desktop Entry]\nName\n";
+
+        // do
+        let actual = DesktopEntry::parse(content);
+
+        // verify
+        assert_eq!(actual, Err(ParseError::new(2, "line is not key=value")))
+    }
+
+    #[test]
+    fn split_program_from_exec() {
+        // arrange
+        // do
+        // verify
+        assert_eq!(
+            split_exec_program(r#""/opt/emacs \"29\"/100%%/emacsclient" --no-wait %u"#),
+            Some((
+                String::from(r#"/opt/emacs "29"/100%/emacsclient"#),
+                " --no-wait %u"
+            ))
+        );
+        assert_eq!(
+            split_exec_program("emacsclient %u"),
+            Some((String::from("emacsclient"), " %u"))
+        );
+        assert_eq!(split_exec_program(r#""emacsclient"#), None);
+    }
+}
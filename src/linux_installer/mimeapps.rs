@@ -0,0 +1,327 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DEFAULT_APPLICATIONS: &str = "Default Applications";
+pub const ADDED_ASSOCIATIONS: &str = "Added Associations";
+pub const REMOVED_ASSOCIATIONS: &str = "Removed Associations";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Group(String),
+    Entry { key: String, value: String },
+    // comment, blank line or anything not to be understood. Kept as is.
+    Other(String),
+}
+
+// Model of mimeapps.list defined in the MIME Applications Associations specification.
+// Lines not touched by editing are written back as they were read.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MimeApps {
+    lines: Vec<Line>,
+}
+
+impl MimeApps {
+    pub fn parse(content: &str) -> MimeApps {
+        let lines = content
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+
+                if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                    return Line::Group(String::from(&trimmed[1..trimmed.len() - 1]));
+                }
+
+                match line.split_once('=') {
+                    Some((key, value)) if !trimmed.starts_with('#') => Line::Entry {
+                        key: String::from(key.trim()),
+                        value: String::from(value.trim()),
+                    },
+                    _ => Line::Other(String::from(line)),
+                }
+            })
+            .collect();
+
+        MimeApps { lines }
+    }
+
+    // read mimeapps.list. Return empty list if the file does not exist.
+    pub fn read(path: &Path) -> io::Result<MimeApps> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(MimeApps::parse(&content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(MimeApps::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, self.to_string())
+    }
+
+    // index of entry for the key in the group
+    fn find_entry(&self, group: &str, key: &str) -> Option<usize> {
+        let mut current_group = None;
+
+        for (index, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Group(name) => current_group = Some(name.as_str()),
+                Line::Entry { key: k, .. } if current_group == Some(group) && k == key => {
+                    return Some(index)
+                }
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    // desktop file IDs for the MIME type in the group
+    pub fn get(&self, group: &str, mime_type: &str) -> Option<Vec<String>> {
+        self.find_entry(group, mime_type)
+            .map(|index| match &self.lines[index] {
+                Line::Entry { value, .. } => value
+                    .split(';')
+                    .filter(|v| !v.is_empty())
+                    .map(String::from)
+                    .collect(),
+                _ => unreachable!(),
+            })
+    }
+
+    pub fn set(&mut self, group: &str, mime_type: &str, desktop_ids: &[&str]) {
+        let value = desktop_ids
+            .iter()
+            .map(|v| format!("{};", v))
+            .collect::<String>();
+
+        if let Some(index) = self.find_entry(group, mime_type) {
+            self.lines[index] = Line::Entry {
+                key: String::from(mime_type),
+                value,
+            };
+            return;
+        }
+
+        let entry = Line::Entry {
+            key: String::from(mime_type),
+            value,
+        };
+        match self.group_insert_position(group) {
+            Some(index) => self.lines.insert(index, entry),
+            None => {
+                if matches!(self.lines.last(), Some(Line::Entry { .. })) {
+                    self.lines.push(Line::Other(String::new()));
+                }
+                self.lines.push(Line::Group(String::from(group)));
+                self.lines.push(entry);
+            }
+        }
+    }
+
+    // remove the MIME type from the group. Return true if removed.
+    pub fn remove(&mut self, group: &str, mime_type: &str) -> bool {
+        match self.find_entry(group, mime_type) {
+            Some(index) => {
+                self.lines.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // position just after the last entry of the group
+    fn group_insert_position(&self, group: &str) -> Option<usize> {
+        let start = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, Line::Group(name) if name == group))?;
+        let mut position = start + 1;
+
+        for (index, line) in self.lines.iter().enumerate().skip(start + 1) {
+            match line {
+                Line::Group(_) => break,
+                Line::Entry { .. } => position = index + 1,
+                Line::Other(_) => (),
+            }
+        }
+
+        Some(position)
+    }
+
+    pub fn default_application(&self, mime_type: &str) -> Option<String> {
+        self.get(DEFAULT_APPLICATIONS, mime_type)
+            .and_then(|ids| ids.into_iter().next())
+    }
+
+    // make the desktop file default application for the MIME type, and add it to associations.
+    // It is removed from removed associations, that make resolvers ignore the association.
+    pub fn set_default_application(&mut self, mime_type: &str, desktop_id: &str) {
+        self.set(DEFAULT_APPLICATIONS, mime_type, &[desktop_id]);
+        self.add_association(mime_type, desktop_id);
+        self.remove_id(REMOVED_ASSOCIATIONS, mime_type, desktop_id);
+    }
+
+    // prepend the desktop file to added associations of the MIME type
+    pub fn add_association(&mut self, mime_type: &str, desktop_id: &str) {
+        let mut ids = self.get(ADDED_ASSOCIATIONS, mime_type).unwrap_or_default();
+        ids.retain(|v| v != desktop_id);
+        ids.insert(0, String::from(desktop_id));
+
+        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+        self.set(ADDED_ASSOCIATIONS, mime_type, &ids);
+    }
+
+    // remove the desktop file from added associations of the MIME type
+    pub fn remove_association(&mut self, mime_type: &str, desktop_id: &str) {
+        self.remove_id(ADDED_ASSOCIATIONS, mime_type, desktop_id);
+    }
+
+    // remove the desktop file from the MIME type in the group. The key is removed when no
+    // desktop file remains.
+    fn remove_id(&mut self, group: &str, mime_type: &str, desktop_id: &str) {
+        let mut ids = match self.get(group, mime_type) {
+            Some(ids) => ids,
+            None => return,
+        };
+        ids.retain(|v| v != desktop_id);
+
+        if ids.is_empty() {
+            self.remove(group, mime_type);
+        } else {
+            let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+            self.set(group, mime_type, &ids);
+        }
+    }
+}
+
+impl Display for MimeApps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Group(name) => writeln!(f, "[{}]", name)?,
+                Line::Entry { key, value } => writeln!(f, "{}={}", key, value)?,
+                Line::Other(line) => writeln!(f, "{}", line)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_default_application_into_empty_list() {
+        // arrange
+        let mut list = MimeApps::default();
+
+        // do
+        list.set_default_application("x-scheme-handler/org-protocol", "org-protocol.desktop");
+
+        // verify
+        assert_eq!(
+            list.to_string(),
+            r#"[Default Applications]
+x-scheme-handler/org-protocol=org-protocol.desktop;
+
+[Added Associations]
+x-scheme-handler/org-protocol=org-protocol.desktop;
+"#
+        )
+    }
+
+    #[test]
+    fn keep_comments_and_unrelated_entries() {
+        // arrange
+        let mut list = MimeApps::parse(
+            r#"# managed by hand
+[Added Associations]
+text/html=firefox.desktop;
+x-scheme-handler/org-protocol=emacsclient.desktop;
+
+[Default Applications]
+# browser
+text/html=firefox.desktop
+x-scheme-handler/org-protocol=emacsclient.desktop
+
+[Unknown Group]
+foo=bar
+"#,
+        );
+
+        // do
+        list.set_default_application("x-scheme-handler/org-protocol", "org-protocol.desktop");
+
+        // verify
+        assert_eq!(
+            list.to_string(),
+            r#"# managed by hand
+[Added Associations]
+text/html=firefox.desktop;
+x-scheme-handler/org-protocol=org-protocol.desktop;emacsclient.desktop;
+
+[Default Applications]
+# browser
+text/html=firefox.desktop
+x-scheme-handler/org-protocol=org-protocol.desktop;
+
+[Unknown Group]
+foo=bar
+"#
+        )
+    }
+
+    #[test]
+    fn remove_from_removed_associations_on_set_default_application() {
+        // arrange
+        let mut list = MimeApps::parse(
+            r#"[Removed Associations]
+x-scheme-handler/org-protocol=org-protocol.desktop;emacsclient.desktop;
+text/html=org-protocol.desktop;
+"#,
+        );
+
+        // do
+        list.set_default_application("x-scheme-handler/org-protocol", "org-protocol.desktop");
+
+        // verify
+        assert_eq!(
+            list.get(REMOVED_ASSOCIATIONS, "x-scheme-handler/org-protocol"),
+            Some(vec![String::from("emacsclient.desktop")])
+        );
+        assert_eq!(
+            list.get(REMOVED_ASSOCIATIONS, "text/html"),
+            Some(vec![String::from("org-protocol.desktop")])
+        );
+
+        // do
+        list.set_default_application("x-scheme-handler/org-protocol", "emacsclient.desktop");
+
+        // verify
+        assert_eq!(
+            list.get(REMOVED_ASSOCIATIONS, "x-scheme-handler/org-protocol"),
+            None
+        );
+    }
+
+    #[test]
+    fn get_default_application() {
+        // arrange
+        let list = MimeApps::parse(
+            "[Default Applications]\nx-scheme-handler/org-protocol=a.desktop;b.desktop\n",
+        );
+
+        // do
+        let actual = list.default_application("x-scheme-handler/org-protocol");
+
+        // verify
+        assert_eq!(actual, Some(String::from("a.desktop")))
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::desktop_entry::DesktopEntry;
+use super::resolve::MIME_CACHE_GROUP;
+use super::xdg;
+
+pub const MIMEINFO_CACHE: &str = "mimeinfo.cache";
+
+// Read desktop entries in the applications directory, with their desktop file IDs
+pub fn read_entries(dir: &Path) -> BTreeMap<String, DesktopEntry> {
+    xdg::desktop_files_in(dir)
+        .into_iter()
+        .filter_map(|(id, path)| {
+            let content = fs::read_to_string(&path).ok()?;
+            DesktopEntry::parse(&content).ok().map(|entry| (id, entry))
+        })
+        .collect()
+}
+
+// Build content of mimeinfo.cache for desktop entries, as update-desktop-database does.
+pub fn build_from(entries: &BTreeMap<String, DesktopEntry>) -> String {
+    let mut cache: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (id, entry) in entries {
+        if entry.get("Hidden").is_some_and(|v| v == "true") {
+            continue;
+        }
+
+        for mime_type in entry.get_list("MimeType").unwrap_or_default() {
+            cache.entry(mime_type).or_default().push(id.clone());
+        }
+    }
+
+    let mut content = format!("[{}]\n", MIME_CACHE_GROUP);
+    for (mime_type, ids) in cache {
+        let _ = writeln!(content, "{}={};", mime_type, ids.join(";"));
+    }
+
+    content
+}
+
+// Build content of mimeinfo.cache for desktop files in the applications directory
+pub fn build(dir: &Path) -> String {
+    build_from(&read_entries(dir))
+}
+
+// path of the cache in the applications directory
+pub fn path_in(dir: &Path) -> PathBuf {
+    dir.join(MIMEINFO_CACHE)
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn build_cache_from_desktop_files() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(
+            "org-protocol.desktop",
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            "emacs/client.desktop",
+            "[Desktop Entry]\nMimeType=text/plain;x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            "hidden.desktop",
+            "[Desktop Entry]\nHidden=true\nMimeType=text/plain;\n",
+        );
+        write("other.desktop", "[Desktop Entry]\nName=Other\n");
+
+        // do
+        let actual = build(dir.path());
+
+        // verify
+        assert_eq!(
+            actual,
+            r#"[MIME Cache]
+text/plain=emacs-client.desktop;
+x-scheme-handler/org-protocol=emacs-client.desktop;org-protocol.desktop;
+"#
+        )
+    }
+}
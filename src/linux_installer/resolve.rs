@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::desktop_entry::DesktopEntry;
+use super::mimeapps::{self, MimeApps};
+use super::xdg::{self, SearchPaths};
+
+pub const MIME_CACHE_GROUP: &str = "MIME Cache";
+
+// Desktop file chosen to handle the MIME type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handler {
+    pub desktop_id: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+// Result of lookup, with the steps that led to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub mime_type: String,
+    pub handler: Option<Handler>,
+    pub steps: Vec<String>,
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Lookup of {}:", self.mime_type)?;
+        for step in &self.steps {
+            writeln!(f, "  {}", step)?;
+        }
+
+        match &self.handler {
+            Some(handler) => {
+                writeln!(
+                    f,
+                    "Handler: {} ({})",
+                    handler.desktop_id,
+                    handler.path.to_string_lossy()
+                )?;
+                writeln!(f, "Reason: {}", handler.reason)
+            }
+            None => writeln!(f, "Handler: none"),
+        }
+    }
+}
+
+// Desktop file visible with its ID. The first one in order of precedence shadows others.
+struct Installed {
+    path: PathBuf,
+    hidden: bool,
+}
+
+struct Lookup {
+    installed: HashMap<String, Installed>,
+    steps: Vec<String>,
+}
+
+impl Lookup {
+    fn new(paths: &SearchPaths) -> Self {
+        let mut installed = HashMap::new();
+
+        for dir in paths.applications_dirs() {
+            for (id, path) in xdg::desktop_files_in(&dir) {
+                if installed.contains_key(&id) {
+                    continue;
+                }
+
+                // desktop entry with Hidden=true is treated as deleted
+                let hidden = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|content| DesktopEntry::parse(&content).ok())
+                    .and_then(|entry| entry.get("Hidden"))
+                    .is_some_and(|v| v == "true");
+                installed.insert(id, Installed { path, hidden });
+            }
+        }
+
+        Lookup {
+            installed,
+            steps: Vec::new(),
+        }
+    }
+
+    // check the desktop file can be used, and record why if it can not
+    fn usable(&mut self, id: &str, removed: &[String], source: &Path) -> Option<PathBuf> {
+        let source = source.to_string_lossy();
+
+        match self.installed.get(id) {
+            None => {
+                self.steps
+                    .push(format!("{} in {} is not installed, skipped", id, source));
+                None
+            }
+            Some(installed) if installed.hidden => {
+                self.steps.push(format!(
+                    "{} in {} is hidden by {}, skipped",
+                    id,
+                    source,
+                    installed.path.to_string_lossy()
+                ));
+                None
+            }
+            Some(_) if removed.iter().any(|v| v == id) => {
+                self.steps.push(format!(
+                    "{} in {} is in Removed Associations, skipped",
+                    id, source
+                ));
+                None
+            }
+            Some(installed) => Some(installed.path.clone()),
+        }
+    }
+}
+
+// Look up the application to handle the MIME type, according to MIME Applications
+// Associations specification.
+pub fn resolve(paths: &SearchPaths, mime_type: &str) -> Resolution {
+    let mut lookup = Lookup::new(paths);
+    let lists = paths
+        .mimeapps_lists()
+        .into_iter()
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let list = MimeApps::read(&path).unwrap_or_default();
+            (path, list)
+        })
+        .collect::<Vec<_>>();
+
+    let handler = find_default(&mut lookup, &lists, mime_type)
+        .or_else(|| find_associated(&mut lookup, &lists, paths, mime_type));
+
+    Resolution {
+        mime_type: String::from(mime_type),
+        handler,
+        steps: lookup.steps,
+    }
+}
+
+fn find_default(
+    lookup: &mut Lookup,
+    lists: &[(PathBuf, MimeApps)],
+    mime_type: &str,
+) -> Option<Handler> {
+    let mut removed = Vec::new();
+
+    for (path, list) in lists {
+        removed.extend(
+            list.get(mimeapps::REMOVED_ASSOCIATIONS, mime_type)
+                .unwrap_or_default(),
+        );
+
+        for id in list
+            .get(mimeapps::DEFAULT_APPLICATIONS, mime_type)
+            .unwrap_or_default()
+        {
+            if let Some(desktop_path) = lookup.usable(&id, &removed, path) {
+                return Some(Handler {
+                    desktop_id: id,
+                    path: desktop_path,
+                    reason: format!("default application in {}", path.to_string_lossy()),
+                });
+            }
+        }
+    }
+
+    lookup
+        .steps
+        .push(String::from("no usable default application"));
+    None
+}
+
+fn find_associated(
+    lookup: &mut Lookup,
+    lists: &[(PathBuf, MimeApps)],
+    paths: &SearchPaths,
+    mime_type: &str,
+) -> Option<Handler> {
+    let mut removed = Vec::new();
+
+    for (path, list) in lists {
+        removed.extend(
+            list.get(mimeapps::REMOVED_ASSOCIATIONS, mime_type)
+                .unwrap_or_default(),
+        );
+
+        for id in list
+            .get(mimeapps::ADDED_ASSOCIATIONS, mime_type)
+            .unwrap_or_default()
+        {
+            if let Some(desktop_path) = lookup.usable(&id, &removed, path) {
+                return Some(Handler {
+                    desktop_id: id,
+                    path: desktop_path,
+                    reason: format!("added association in {}", path.to_string_lossy()),
+                });
+            }
+        }
+    }
+
+    for dir in paths.applications_dirs() {
+        let (source, ids) = match associated_in_cache(&dir, mime_type) {
+            Some(ids) => (dir.join("mimeinfo.cache"), ids),
+            None => (dir.clone(), associated_in_files(&dir, mime_type)),
+        };
+
+        for id in ids {
+            if let Some(desktop_path) = lookup.usable(&id, &removed, &source) {
+                return Some(Handler {
+                    desktop_id: id,
+                    path: desktop_path,
+                    reason: format!(
+                        "MimeType of desktop file listed in {}",
+                        source.to_string_lossy()
+                    ),
+                });
+            }
+        }
+    }
+
+    lookup
+        .steps
+        .push(String::from("no desktop file associated"));
+    None
+}
+
+// desktop file IDs for the MIME type in mimeinfo.cache of the directory
+fn associated_in_cache(dir: &Path, mime_type: &str) -> Option<Vec<String>> {
+    let content = fs::read_to_string(dir.join("mimeinfo.cache")).ok()?;
+
+    Some(
+        MimeApps::parse(&content)
+            .get(MIME_CACHE_GROUP, mime_type)
+            .unwrap_or_default(),
+    )
+}
+
+// desktop file IDs in the directory that declare the MIME type in MimeType key
+pub fn associated_in_files(dir: &Path, mime_type: &str) -> Vec<String> {
+    xdg::desktop_files_in(dir)
+        .into_iter()
+        .filter(|(_, path)| {
+            fs::read_to_string(path)
+                .ok()
+                .and_then(|content| DesktopEntry::parse(&content).ok())
+                .and_then(|entry| entry.get_list("MimeType"))
+                .is_some_and(|types| types.iter().any(|v| v == mime_type))
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const MIME_TYPE: &str = "x-scheme-handler/org-protocol";
+
+    fn search_paths(root: &Path) -> SearchPaths {
+        SearchPaths {
+            desktops: vec![String::from("gnome")],
+            config_home: Some(root.join("config")),
+            config_dirs: vec![root.join("etc")],
+            data_home: Some(root.join("data")),
+            data_dirs: vec![root.join("usr")],
+        }
+    }
+
+    fn write(path: PathBuf, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn prefer_desktop_specific_default_application() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root.join("data/applications/org-protocol.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            root.join("usr/applications/emacsclient.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            root.join("config/gnome-mimeapps.list"),
+            "[Default Applications]\nx-scheme-handler/org-protocol=missing.desktop;emacsclient.desktop\n",
+        );
+        write(
+            root.join("config/mimeapps.list"),
+            "[Default Applications]\nx-scheme-handler/org-protocol=org-protocol.desktop\n",
+        );
+
+        // do
+        let actual = resolve(&search_paths(root), MIME_TYPE);
+
+        // verify
+        let handler = actual.handler.unwrap();
+        assert_eq!(handler.desktop_id, "emacsclient.desktop");
+        assert_eq!(
+            handler.reason,
+            format!(
+                "default application in {}",
+                root.join("config/gnome-mimeapps.list").to_string_lossy()
+            )
+        );
+        assert_eq!(actual.steps.len(), 1);
+    }
+
+    #[test]
+    fn fall_back_to_mimeinfo_cache_without_removed_associations() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root.join("usr/applications/emacsclient.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            root.join("usr/applications/other.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            root.join("usr/applications/mimeinfo.cache"),
+            "[MIME Cache]\nx-scheme-handler/org-protocol=emacsclient.desktop;other.desktop;\n",
+        );
+        write(
+            root.join("config/mimeapps.list"),
+            "[Removed Associations]\nx-scheme-handler/org-protocol=emacsclient.desktop\n",
+        );
+
+        // do
+        let actual = resolve(&search_paths(root), MIME_TYPE);
+
+        // verify
+        let handler = actual.handler.unwrap();
+        assert_eq!(handler.desktop_id, "other.desktop");
+        assert_eq!(handler.path, root.join("usr/applications/other.desktop"));
+    }
+
+    #[test]
+    fn skip_desktop_file_hidden_by_user() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(
+            root.join("usr/applications/emacsclient.desktop"),
+            "[Desktop Entry]\nMimeType=x-scheme-handler/org-protocol;\n",
+        );
+        write(
+            root.join("data/applications/emacsclient.desktop"),
+            "[Desktop Entry]\nHidden=true\n",
+        );
+
+        // do
+        let actual = resolve(&search_paths(root), MIME_TYPE);
+
+        // verify
+        assert_eq!(actual.handler, None);
+    }
+}
@@ -0,0 +1,390 @@
+use std::fmt::{self, Display};
+
+use super::desktop_entry::{DesktopEntry, DesktopEntryGroup, DESKTOP_ENTRY_GROUP};
+
+const KNOWN_KEYS: &[&str] = &[
+    "Type",
+    "Version",
+    "Name",
+    "GenericName",
+    "NoDisplay",
+    "Comment",
+    "Icon",
+    "Hidden",
+    "OnlyShowIn",
+    "NotShowIn",
+    "DBusActivatable",
+    "TryExec",
+    "Exec",
+    "Path",
+    "Terminal",
+    "Actions",
+    "MimeType",
+    "Categories",
+    "Implements",
+    "Keywords",
+    "StartupNotify",
+    "StartupWMClass",
+    "URL",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+
+const LOCALIZABLE_KEYS: &[&str] = &["Name", "GenericName", "Comment", "Icon", "Keywords"];
+
+const BOOLEAN_KEYS: &[&str] = &[
+    "NoDisplay",
+    "Hidden",
+    "DBusActivatable",
+    "Terminal",
+    "StartupNotify",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+
+const LIST_KEYS: &[&str] = &[
+    "OnlyShowIn",
+    "NotShowIn",
+    "Actions",
+    "MimeType",
+    "Categories",
+    "Implements",
+    "Keywords",
+];
+
+const RESERVED_CHARACTERS: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(message: String) -> Self {
+        Issue {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Issue {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+pub fn has_error(issues: &[Issue]) -> bool {
+    issues.iter().any(|v| v.severity == Severity::Error)
+}
+
+// Validate content of desktop file, as desktop-file-validate does.
+pub fn validate_content(content: &str) -> Vec<Issue> {
+    match DesktopEntry::parse(content) {
+        Ok(entry) => validate(&entry),
+        Err(e) => vec![Issue::error(e.to_string())],
+    }
+}
+
+pub fn validate(entry: &DesktopEntry) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    match entry.groups().first() {
+        Some(group) if group.name() == DESKTOP_ENTRY_GROUP => (),
+        _ => issues.push(Issue::error(format!(
+            "first group must be \"{}\"",
+            DESKTOP_ENTRY_GROUP
+        ))),
+    }
+
+    for group in entry.groups() {
+        let name = group.name();
+        if name != DESKTOP_ENTRY_GROUP
+            && !name.starts_with("Desktop Action ")
+            && !name.starts_with("X-")
+        {
+            issues.push(Issue::error(format!(
+                "group \"{}\" is not defined, groups extending the format should start with \"X-\"",
+                name
+            )));
+        }
+        validate_values(group, &mut issues);
+    }
+
+    if let Some(group) = entry.group(DESKTOP_ENTRY_GROUP) {
+        validate_main_group(group, &mut issues);
+    }
+
+    issues
+}
+
+fn validate_main_group(group: &DesktopEntryGroup, issues: &mut Vec<Issue>) {
+    for (key, _) in group.keys() {
+        if !KNOWN_KEYS.contains(&key) && !key.starts_with("X-") {
+            issues.push(Issue::error(format!(
+                "key \"{}\" is not defined, keys extending the format should start with \"X-\"",
+                key
+            )));
+        }
+    }
+
+    for key in &["Type", "Name"] {
+        if group.get_raw(key, None).is_none() {
+            issues.push(Issue::error(format!("required key \"{}\" is missing", key)));
+        }
+    }
+
+    let dbus_activatable = group.get("DBusActivatable").as_deref() == Some("true");
+    match group.get("Type").as_deref() {
+        Some("Application") if group.get_raw("Exec", None).is_none() && !dbus_activatable => issues
+            .push(Issue::error(String::from(
+                "key \"Exec\" is required for Type=Application",
+            ))),
+        Some("Link") if group.get_raw("URL", None).is_none() => issues.push(Issue::error(
+            String::from("key \"URL\" is required for Type=Link"),
+        )),
+        Some("Application") | Some("Link") | Some("Directory") | None => (),
+        Some(other) => issues.push(Issue::error(format!(
+            "value \"{}\" of key \"Type\" must be Application, Link or Directory",
+            other
+        ))),
+    }
+
+    let mime_types = group.get_list("MimeType").unwrap_or_default();
+    for mime_type in &mime_types {
+        if !valid_mime_type(mime_type) {
+            issues.push(Issue::error(format!(
+                "value \"{}\" in key \"MimeType\" is not a valid MIME type",
+                mime_type
+            )));
+        }
+    }
+
+    if let Some(exec) = group.get("Exec") {
+        let handles_url = mime_types
+            .iter()
+            .any(|v| v.starts_with("x-scheme-handler/"));
+        validate_exec(&exec, handles_url, issues);
+    }
+}
+
+// check escape sequences and types of values in the group
+fn validate_values(group: &DesktopEntryGroup, issues: &mut Vec<Issue>) {
+    for (key, locale) in group.keys() {
+        let raw = group.get_raw(key, locale).unwrap_or_default();
+        let name = match locale {
+            Some(locale) => format!("{}[{}]", key, locale),
+            None => String::from(key),
+        };
+        let is_list = LIST_KEYS.contains(&key);
+
+        if locale.is_some() && !LOCALIZABLE_KEYS.contains(&key) && !key.starts_with("X-") {
+            issues.push(Issue::error(format!(
+                "key \"{}\" can not be localized",
+                key
+            )));
+        }
+
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                continue;
+            }
+            match chars.next() {
+                Some('s') | Some('n') | Some('t') | Some('r') | Some('\\') => (),
+                Some(';') if is_list => (),
+                Some(c) => issues.push(Issue::error(format!(
+                    "value of key \"{}\" contains invalid escape sequence \"\\{}\"",
+                    name, c
+                ))),
+                None => issues.push(Issue::error(format!(
+                    "value of key \"{}\" ends with incomplete escape sequence",
+                    name
+                ))),
+            }
+        }
+
+        if BOOLEAN_KEYS.contains(&key) && raw != "true" && raw != "false" {
+            issues.push(Issue::error(format!(
+                "value \"{}\" of boolean key \"{}\" must be true or false",
+                raw, name
+            )));
+        }
+
+        if is_list && !raw.is_empty() && !raw.ends_with(';') {
+            issues.push(Issue::warning(format!(
+                "value of list key \"{}\" does not end with semicolon",
+                name
+            )));
+        }
+    }
+}
+
+fn valid_mime_type(mime_type: &str) -> bool {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+
+    match mime_type.split_once('/') {
+        Some((media, sub)) => valid_part(media) && valid_part(sub),
+        None => false,
+    }
+}
+
+// Check quoting and field codes of Exec key. The value is already unescaped as string.
+fn validate_exec(exec: &str, handles_url: bool, issues: &mut Vec<Issue>) {
+    let mut field_codes = Vec::new();
+    let mut chars = exec.chars().peekable();
+    let mut in_quote = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quote = !in_quote,
+            '\\' if in_quote => match chars.next() {
+                Some('"') | Some('`') | Some('$') | Some('\\') => (),
+                Some(c) => issues.push(Issue::error(format!(
+                    "Exec contains invalid escape \"\\{}\" in quoted argument",
+                    c
+                ))),
+                None => (),
+            },
+            '%' => match chars.next() {
+                Some('%') => (),
+                Some(code) if in_quote => issues.push(Issue::error(format!(
+                    "field code \"%{}\" must not be used inside quoted argument of Exec",
+                    code
+                ))),
+                Some(code @ 'f') | Some(code @ 'F') | Some(code @ 'u') | Some(code @ 'U') => {
+                    field_codes.push(code)
+                }
+                Some('i') | Some('c') | Some('k') => (),
+                Some(code @ 'd') | Some(code @ 'D') | Some(code @ 'n') | Some(code @ 'N')
+                | Some(code @ 'v') | Some(code @ 'm') => issues.push(Issue::warning(format!(
+                    "field code \"%{}\" in Exec is deprecated",
+                    code
+                ))),
+                Some(code) => issues.push(Issue::error(format!(
+                    "field code \"%{}\" in Exec is not valid",
+                    code
+                ))),
+                None => issues.push(Issue::error(String::from(
+                    "Exec ends with incomplete field code",
+                ))),
+            },
+            ' ' | '\t' | '\n' if !in_quote => (),
+            c if !in_quote && RESERVED_CHARACTERS.contains(&c) => issues.push(Issue::error(
+                format!("reserved character \"{}\" in Exec must be quoted", c),
+            )),
+            _ => (),
+        }
+    }
+
+    if in_quote {
+        issues.push(Issue::error(String::from("Exec has unterminated quote")));
+    }
+
+    if field_codes.len() > 1 {
+        issues.push(Issue::error(String::from(
+            "Exec must not contain more than one of %f, %F, %u and %U",
+        )));
+    }
+
+    if handles_url && !field_codes.iter().any(|c| *c == 'u' || *c == 'U') {
+        issues.push(Issue::warning(String::from(
+            "Exec should have %u or %U to receive URL of x-scheme-handler",
+        )));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_org_protocol_entry() {
+        // arrange
+        let content = r#"[Desktop Entry]
+Name=Org-Protocol
+Exec="/opt/emacs 29/bin/emacsclient" --socket-name=work %u
+Icon=emacs-icon
+Type=Application
+Terminal=false
+MimeType=x-scheme-handler/org-protocol;
+X-OrgRoamProtocol-PreviousHandler=emacsclient.desktop
+"#;
+
+        // do
+        let actual = validate_content(content);
+
+        // verify
+        assert_eq!(actual, vec![])
+    }
+
+    #[test]
+    fn report_errors_and_warnings() {
+        // arrange
+        let content = r#"[Desktop Entry]
+Name[ja]=オルグ
+Exec=emacsclient %f %x "%u" a|b
+Type=Program
+Terminal=yes
+Comment=a\qb
+MimeType=x-scheme-handler/org-protocol
+Foo=bar
+"#;
+
+        // do
+        let actual = validate_content(content);
+
+        // verify
+        let messages = actual.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "error: value \"yes\" of boolean key \"Terminal\" must be true or false",
+                "error: value of key \"Comment\" contains invalid escape sequence \"\\q\"",
+                "warning: value of list key \"MimeType\" does not end with semicolon",
+                "error: key \"Foo\" is not defined, keys extending the format should start with \"X-\"",
+                "error: required key \"Name\" is missing",
+                "error: value \"Program\" of key \"Type\" must be Application, Link or Directory",
+                "error: field code \"%x\" in Exec is not valid",
+                "error: field code \"%u\" must not be used inside quoted argument of Exec",
+                "error: reserved character \"|\" in Exec must be quoted",
+                "warning: Exec should have %u or %U to receive URL of x-scheme-handler",
+            ]
+        )
+    }
+
+    #[test]
+    fn report_parse_error() {
+        // arrange
+        // do
+        let actual = validate_content("Name=foo\n");
+
+        // verify
+        assert!(has_error(&actual));
+    }
+}
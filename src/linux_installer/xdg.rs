@@ -0,0 +1,179 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dirs::home_dir;
+
+// Get directory from the environment variable according to XDG Base Directory Specification.
+// Unset, empty or relative path is ignored.
+fn directory_from_env(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+// Get list of directories from the environment variable. Relative paths are ignored, and
+// the default is used when no valid directory is given.
+fn directories_from_env(name: &str, default: &[&str]) -> Vec<PathBuf> {
+    let dirs = env::var_os(name)
+        .map(|value| {
+            env::split_paths(&value)
+                .filter(|path| path.is_absolute())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if dirs.is_empty() {
+        default.iter().map(PathBuf::from).collect()
+    } else {
+        dirs
+    }
+}
+
+// $XDG_DATA_HOME, or $HOME/.local/share
+pub fn data_home() -> Option<PathBuf> {
+    directory_from_env("XDG_DATA_HOME")
+        .or_else(|| home_dir().map(|home| home.join(".local").join("share")))
+}
+
+// $XDG_DATA_DIRS in order of preference
+pub fn data_dirs() -> Vec<PathBuf> {
+    directories_from_env("XDG_DATA_DIRS", &["/usr/local/share", "/usr/share"])
+}
+
+// $XDG_CONFIG_DIRS in order of preference
+pub fn config_dirs() -> Vec<PathBuf> {
+    directories_from_env("XDG_CONFIG_DIRS", &["/etc/xdg"])
+}
+
+// $XDG_CONFIG_HOME, or $HOME/.config
+pub fn config_home() -> Option<PathBuf> {
+    directory_from_env("XDG_CONFIG_HOME").or_else(|| home_dir().map(|home| home.join(".config")))
+}
+
+// path of mimeapps.list for the current user
+pub fn user_mimeapps_list() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("mimeapps.list"))
+}
+
+// path of mimeapps.list for all users
+pub fn system_mimeapps_list() -> Option<PathBuf> {
+    config_dirs()
+        .into_iter()
+        .next()
+        .map(|dir| dir.join("mimeapps.list"))
+}
+
+// directory of desktop entries for the current user
+pub fn user_applications_dir() -> Option<PathBuf> {
+    data_home().map(|dir| dir.join("applications"))
+}
+
+// directory of desktop entries for all users. Most preferred directory in $XDG_DATA_DIRS is
+// used.
+pub fn system_applications_dir() -> Option<PathBuf> {
+    data_dirs()
+        .into_iter()
+        .next()
+        .map(|dir| dir.join("applications"))
+}
+
+// desktop names in $XDG_CURRENT_DESKTOP, lowercased to find $desktop-mimeapps.list
+pub fn current_desktops() -> Vec<String> {
+    env::var("XDG_CURRENT_DESKTOP")
+        .map(|value| {
+            value
+                .split(':')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Directories to look up desktop entries and MIME associations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPaths {
+    pub desktops: Vec<String>,
+    pub config_home: Option<PathBuf>,
+    pub config_dirs: Vec<PathBuf>,
+    pub data_home: Option<PathBuf>,
+    pub data_dirs: Vec<PathBuf>,
+}
+
+impl SearchPaths {
+    pub fn from_env() -> Self {
+        SearchPaths {
+            desktops: current_desktops(),
+            config_home: config_home(),
+            config_dirs: config_dirs(),
+            data_home: data_home(),
+            data_dirs: data_dirs(),
+        }
+    }
+
+    // mimeapps.list files in order of precedence
+    pub fn mimeapps_lists(&self) -> Vec<PathBuf> {
+        let config_dirs = self.config_home.iter().chain(self.config_dirs.iter());
+        let applications_dirs = self.applications_dirs();
+        let mut lists = Vec::new();
+
+        for dir in config_dirs.cloned().chain(applications_dirs) {
+            for desktop in &self.desktops {
+                lists.push(dir.join(format!("{}-mimeapps.list", desktop)));
+            }
+            lists.push(dir.join("mimeapps.list"));
+        }
+
+        lists
+    }
+
+    // applications directories in order of precedence
+    pub fn applications_dirs(&self) -> Vec<PathBuf> {
+        self.data_home
+            .iter()
+            .chain(self.data_dirs.iter())
+            .map(|dir| dir.join("applications"))
+            .collect()
+    }
+}
+
+// Desktop file IDs and paths in the applications directory. ID is the relative path from the
+// directory, with `/` replaced by `-`.
+pub fn desktop_files_in(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    collect_desktop_files(dir, "", &mut files);
+    files.sort();
+    files
+}
+
+// Desktop file ID of the path in the applications directory, or None if it is not in the
+// directory
+pub fn desktop_file_id(dir: &Path, path: &Path) -> Option<String> {
+    let names = path
+        .strip_prefix(dir)
+        .ok()?
+        .iter()
+        .map(|v| v.to_string_lossy())
+        .collect::<Vec<_>>();
+
+    Some(names.join("-"))
+}
+
+fn collect_desktop_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if path.is_dir() {
+            collect_desktop_files(&path, &format!("{}{}-", prefix, name), files);
+        } else if name.ends_with(".desktop") {
+            files.push((format!("{}{}", prefix, name), path));
+        }
+    }
+}
@@ -1,230 +1,856 @@
-use std::fs::remove_file;
-use std::fs::File;
-use std::io;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Cursor;
-use std::io::Write;
-use std::path::Path;
-use std::process::Command;
-
-use quick_xml::events::BytesEnd;
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use quick_xml::Writer;
-use tempfile::Builder;
-
-use crate::config::MacOSConfig;
+use std::path::{Path, PathBuf};
+
+use crate::command::{CommandRunner, LoggingCommandRunner, SystemCommandRunner};
+use crate::config::{EmacsclientOptions, MacOSConfig};
+use crate::plan::{Action, Plan};
+use crate::stable_path;
 
 use super::InstallerResult;
 use super::RoamProtocolInstaller;
 
-fn make_org_protocol_script(path: &Path) -> String {
-    let script = format!(
+pub mod bundle;
+pub mod emacsclient;
+pub mod launch_services;
+pub mod plist;
+
+use plist::{Dict, Value};
+
+// quote the string as AppleScript string literal
+pub fn applescript_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Shell command line to run emacsclient, without URL. emacsclient must not wait the server,
+// because the handler blocks until it exits.
+pub fn emacsclient_command_line(options: &EmacsclientOptions) -> String {
+    let mut args = vec![options.path.to_string_lossy().to_string()];
+    args.push(String::from("--no-wait"));
+    args.extend(options.arguments().into_iter().filter(|v| v != "--no-wait"));
+
+    args.iter()
+        .map(|v| bundle::shell_quote(v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// parse AppleScript string literal
+fn applescript_unquote(literal: &str) -> Option<String> {
+    let mut chars = literal.strip_prefix('"')?.chars();
+    let mut value = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => value.push(chars.next()?),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+// Rewrite version pinned emacsclient at the head of the command line to stable path. Return
+// the stable path and the command line rewritten, or None if nothing to repair.
+fn repair_command(
+    command: &str,
+    home: Option<&Path>,
+) -> InstallerResult<Option<(PathBuf, String)>> {
+    let (program, rest) =
+        bundle::split_shell_word(command).ok_or("Can not parse command line of emacsclient")?;
+
+    Ok(
+        stable_path::repaired(Path::new(&program), home)?.map(|stable| {
+            let command = format!("{}{}", bundle::shell_quote(&stable.to_string_lossy()), rest);
+            (stable, command)
+        }),
+    )
+}
+
+// repair the command in AppleScript written by make_org_protocol_script
+fn repair_applescript(
+    source: &str,
+    home: Option<&Path>,
+) -> InstallerResult<Option<(PathBuf, String)>> {
+    let literal = source
+        .lines()
+        .find_map(|v| v.trim().strip_prefix("set EC to "))
+        .ok_or("AppleScript does not set command of emacsclient")?;
+    let command = applescript_unquote(literal).ok_or("Can not parse command of emacsclient")?;
+
+    Ok(repair_command(&command, home)?.map(|(stable, command)| {
+        (
+            stable,
+            source.replacen(literal, &applescript_quote(&command), 1),
+        )
+    }))
+}
+
+fn make_org_protocol_script(config: &MacOSConfig) -> String {
+    let command = format!("{} ", emacsclient_command_line(&config.emacsclient));
+    let mut script = format!(
         r#"
 on open location this_URL
-    set EC to "{} --no-wait "
+    set EC to {}
     set filePath to quoted form of this_URL
     do shell script EC & filePath
-    tell application "Emacs" to activate
-end open location
 "#,
-        path.to_str().unwrap()
+        applescript_quote(&command)
     );
 
+    if let Some(app) = &config.activate {
+        script.push_str(&format!(
+            "    tell application {} to activate\n",
+            applescript_quote(app)
+        ));
+    }
+    script.push_str("end open location\n");
+
     script
 }
 
-const PLIST_ELEMENTS: &str = r#"<key>CFBundleURLTypes</key>
-<array>
-  <dict>
-    <key>CFBundleURLName</key>
-    <string>org-protocol handler</string>
-    <key>CFBundleURLSchemes</key>
-    <array>
-      <string>org-protocol</string>
-    </array>
-  </dict>
-</array>
-"#;
+pub const ORG_PROTOCOL_SCHEME: &str = "org-protocol";
+
+// entry of CFBundleURLTypes to handle org-protocol
+pub fn org_protocol_url_type() -> Value {
+    let mut dict = Dict::new();
+    dict.insert("CFBundleURLName", Value::from("org-protocol handler"));
+    dict.insert(
+        "CFBundleURLSchemes",
+        Value::Array(vec![Value::from(ORG_PROTOCOL_SCHEME)]),
+    );
+
+    Value::Dict(dict)
+}
+
+// Add URL type for org-protocol into top level dict of Info.plist. Other URL types are kept,
+// and nothing is changed if org-protocol is already registered. Return true if changed.
+pub fn add_url_type(info: &mut Dict) -> bool {
+    let types = match info
+        .get_mut("CFBundleURLTypes")
+        .and_then(|v| v.as_array_mut())
+    {
+        Some(types) => types,
+        None => {
+            info.insert(
+                "CFBundleURLTypes",
+                Value::Array(vec![org_protocol_url_type()]),
+            );
+            return true;
+        }
+    };
+
+    let registered = types.iter().any(|url_type| {
+        url_type
+            .as_dict()
+            .and_then(|v| v.get("CFBundleURLSchemes"))
+            .and_then(|v| v.as_array())
+            .is_some_and(|schemes| {
+                schemes
+                    .iter()
+                    .any(|v| v.as_str() == Some(ORG_PROTOCOL_SCHEME))
+            })
+    });
+    if registered {
+        return false;
+    }
+
+    types.push(org_protocol_url_type());
+    true
+}
+
+// keys to run the application without Dock icon and menu bar
+const BACKGROUND_ONLY_KEYS: &[&str] = &["LSUIElement", "LSBackgroundOnly"];
+
+// Make the application run in background only, not to be shown in Dock and app switcher.
+// Return true if changed.
+pub fn set_background_only(info: &mut Dict) -> bool {
+    let mut changed = false;
+
+    for key in BACKGROUND_ONLY_KEYS {
+        if info.get(key).and_then(|v| v.as_bool()) != Some(true) {
+            info.insert(key, Value::Boolean(true));
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+// Keys of applet_info in Info.plist of the installed bundle
+fn installed_applet_info(installed: &Dict) -> Dict {
+    let mut info = Dict::new();
+    let keys = ["CFBundleIdentifier", launch_services::PREVIOUS_HANDLERS_KEY];
+
+    for key in keys.iter().chain(BACKGROUND_ONLY_KEYS) {
+        if let Some(value) = installed.get(key) {
+            info.insert(key, value.clone());
+        }
+    }
+    info
+}
 
 pub fn new(config: MacOSConfig) -> Box<dyn RoamProtocolInstaller> {
-    Box::new(MacOSRoamProtocolInstaller::new(config))
+    Box::new(MacOSRoamProtocolInstaller::new(
+        config,
+        Box::new(SystemCommandRunner),
+    ))
 }
 
 struct MacOSRoamProtocolInstaller {
     config: MacOSConfig,
+    // runs commands that do not change the system, while planning
+    runner: Box<dyn CommandRunner>,
 }
 
 impl MacOSRoamProtocolInstaller {
-    pub fn new(config: MacOSConfig) -> Self {
-        MacOSRoamProtocolInstaller { config }
-    }
-
-    fn write_protocol_script(&self, writer: &mut dyn Write) -> InstallerResult<()> {
-        let script = make_org_protocol_script(self.config.emacsclient_path.as_path());
-        writer.write_all(script.as_bytes())?;
-
-        Ok(())
-    }
-
-    fn compile_client_script(&self, path: &Path) -> InstallerResult<()> {
-        let mut child = Command::new("osacompile")
-            .args(&[
-                "-o",
-                "/Applications/OrgProtocolClient.app",
-                path.to_str().unwrap(),
-            ])
-            .spawn()?;
-        child.wait()?;
-
-        Ok(())
-    }
-
-    fn rewrite_plist(&self, original: &mut dyn BufRead) -> InstallerResult<Vec<u8>> {
-        let mut reader = Reader::from_reader(original);
-        let mut writer = Writer::new(Cursor::new(Vec::new()));
-        let mut buf = Vec::new();
-
-        loop {
-            match reader.read_event(&mut buf) {
-                Ok(Event::End(ref e)) if e.name() == b"dict" => {
-                    self.write_url_association_info(&mut writer)?;
-                    writer.write_event(Event::End(BytesEnd::borrowed(b"dict")))
-                }
-                Ok(Event::Eof) => break,
-                Ok(e) => writer.write_event(&e),
-                Err(e) => Err(e),
-            }?;
-            buf.clear();
+    pub fn new(config: MacOSConfig, runner: Box<dyn CommandRunner>) -> Self {
+        MacOSRoamProtocolInstaller { config, runner }
+    }
+
+    // Keys that Info.plist of the applet must have, other than URL types merged into the
+    // existing ones. The bundle identifier is replaced with ours, to find the bundle on
+    // uninstall, and handlers replaced by it are recorded.
+    fn applet_info(&self, previous_handlers: Vec<Value>) -> Dict {
+        let mut info = Dict::new();
+        info.insert(
+            "CFBundleIdentifier",
+            Value::from(self.config.bundle_identifier.as_str()),
+        );
+        if self.config.background_only {
+            set_background_only(&mut info);
+        }
+        if !previous_handlers.is_empty() {
+            info.insert(
+                launch_services::PREVIOUS_HANDLERS_KEY,
+                Value::Array(previous_handlers),
+            );
         }
 
-        Ok(writer.into_inner().into_inner())
+        info
     }
 
-    fn write_url_association_info<W>(&self, writer: &mut Writer<W>) -> InstallerResult<()>
-    where
-        W: Write,
-    {
-        let mut reader = Reader::from_str(PLIST_ELEMENTS);
-        let mut buf = Vec::new();
-
-        loop {
-            match reader.read_event(&mut buf) {
-                Ok(Event::Eof) => break,
-                Ok(e) => writer.write_event(&e),
-                Err(e) => Err(e),
-            }?;
-            buf.clear()
+    // Handlers of org-protocol to put back on uninstall. Ones recorded in the installed bundle
+    // are kept while it is the handler.
+    fn previous_handlers(&self) -> InstallerResult<Vec<Value>> {
+        let secure = launch_services::read_secure_plist(&self.config.launch_services_plist)?;
+        let others = launch_services::other_handlers(
+            &secure,
+            ORG_PROTOCOL_SCHEME,
+            &self.config.bundle_identifier,
+        );
+        if !others.is_empty() {
+            return Ok(others);
+        }
+
+        Ok(self.recorded_handlers())
+    }
+
+    // handlers of org-protocol recorded in Info.plist of the installed bundle
+    fn recorded_handlers(&self) -> Vec<Value> {
+        bundle::read_info(&self.config.bundle_path())
+            .and_then(|v| {
+                v.get(launch_services::PREVIOUS_HANDLERS_KEY)
+                    .and_then(|v| v.as_array())
+                    .cloned()
+            })
+            .unwrap_or_default()
+    }
+
+    // Build client application with Script Editor, and associate URL to it. The script is
+    // given line by line, not to leave temporary file. The keys are set into Info.plist.
+    fn compile_applet(&self, source: &str, info: &Dict) -> Vec<Action> {
+        let bundle = self.config.bundle_path();
+        let mut args = vec![String::from("-o"), bundle.to_string_lossy().to_string()];
+        for line in source.lines().filter(|v| !v.trim().is_empty()) {
+            args.push(String::from("-e"));
+            args.push(String::from(line));
         }
-        Ok(())
+
+        let mut actions = vec![Action::RunCommand {
+            program: PathBuf::from("osacompile"),
+            args,
+            outputs: vec![bundle.clone()],
+            undo_args: None,
+        }];
+        let info_plist = bundle.join("Contents/Info.plist");
+        actions.extend(Action::plist_edits(&info_plist, &Dict::new(), info));
+        actions.push(Action::AddUrlType(info_plist));
+        actions
+    }
+
+    // register the bundle to LaunchServices, and make it default handler of org-protocol
+    fn register_handler(&self) -> InstallerResult<Vec<Action>> {
+        let mut actions = vec![launch_services::register(&self.config.bundle_path())];
+
+        let identifier = self.config.bundle_identifier.as_str();
+        let edits =
+            launch_services::edit_secure_plist(self.config.launch_services_plist.as_path(), |v| {
+                launch_services::set_default_handler(v, ORG_PROTOCOL_SCHEME, identifier)
+            })?;
+        if edits.is_empty() {
+            println!("{} is already default handler of org-protocol", identifier);
+        }
+        actions.extend(edits);
+
+        Ok(actions)
     }
 }
 
 impl RoamProtocolInstaller for MacOSRoamProtocolInstaller {
-    fn install(&mut self) -> InstallerResult<()> {
-        println!("Building client application via Script Editor...");
-        let mut script_temp_file = Builder::new()
-            .prefix("org-protocol-script")
-            .suffix(".scpt")
-            .rand_bytes(8)
-            .tempfile()?;
-        self.write_protocol_script(script_temp_file.as_file_mut())?;
-
-        self.compile_client_script(script_temp_file.path())?;
-
-        println!("Editing plist to associate URL to application...");
-        let path = Path::new("/Applications/OrgProtocolClient.app/Contents/Info.plist");
-        let buf;
-        {
-            let file = File::open(path)?;
-            let mut reader = BufReader::new(file);
-            buf = self.rewrite_plist(&mut reader)?;
-        };
-        let mut file = File::create(path)?;
-        file.write_all(&buf)?;
+    fn install(&mut self) -> InstallerResult<Plan> {
+        let mut plan = Plan::new();
+
+        if let Some(search) = &self.config.emacsclient_search {
+            println!("Searching emacsclient...");
+            let mut runner = LoggingCommandRunner::new(self.runner.as_mut());
+            let found = emacsclient::detect(search, &mut runner);
+            for (program, args) in &runner.commands {
+                plan.push_query(program, args);
+            }
+            let found = found?;
+            println!("Use {} ({})", found.path.display(), found.version);
+            // warnings of config are shown before the search
+            let home = dirs::home_dir();
+            if let Some(warning) = stable_path::warning(&found.path, home.as_deref()) {
+                println!("warning: {}", warning);
+            }
+            self.config.emacsclient.path = found.path;
+        }
 
-        println!("Need associating URL to created application.");
-        println!("Please run application located /Applications/OrgRoamProtocol.app by hand.");
-        Ok(())
+        let info = self.applet_info(self.previous_handlers()?);
+        plan.extend(self.compile_applet(&make_org_protocol_script(&self.config), &info));
+        plan.extend(self.register_handler()?);
+
+        Ok(plan)
     }
 
-    fn uninstall(&mut self) -> InstallerResult<()> {
-        let path = Path::new("/Applications/OrgProtocolClient.app");
+    fn uninstall(&mut self) -> InstallerResult<Plan> {
+        let path = self.config.bundle_path();
+        let mut plan = Plan::new();
+
+        if bundle::has_identifier(&path, &self.config.bundle_identifier) {
+            plan.push(launch_services::unregister(&path));
+        }
+        match bundle::removal(&path, &self.config.bundle_identifier)? {
+            Some(action) => plan.push(action),
+            None => println!("{} does not exist, nothing to remove", path.display()),
+        }
+
+        let identifier = self.config.bundle_identifier.as_str();
+        let previous = self.recorded_handlers();
+        plan.extend(launch_services::edit_secure_plist(
+            self.config.launch_services_plist.as_path(),
+            |v| {
+                launch_services::remove_default_handler(
+                    v,
+                    ORG_PROTOCOL_SCHEME,
+                    identifier,
+                    &previous,
+                )
+            },
+        )?);
+        Ok(plan)
+    }
 
-        match remove_file(&path) {
-            Ok(_) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
-            Err(e) => Err(Box::new(e)),
+    fn repair(&mut self) -> InstallerResult<Plan> {
+        let bundle_path = self.config.bundle_path();
+        if !bundle::has_identifier(&bundle_path, &self.config.bundle_identifier) {
+            return Err(format!(
+                "{} is not installed by this installer",
+                bundle_path.display()
+            )
+            .into());
         }
+        let home = dirs::home_dir();
+        // keys given by flags at installation are kept, not to change the application
+        let info = bundle::read_info(&bundle_path)
+            .map(|v| installed_applet_info(&v))
+            .unwrap_or_default();
+
+        let main = bundle_path.join("Contents/Resources/Scripts/main.scpt");
+        let args = [main.to_string_lossy().to_string()];
+        let source = self.runner.run(Path::new("osadecompile"), &args)?;
+        let repaired = repair_applescript(&source, home.as_deref())?
+            .map(|(stable, source)| (stable, self.compile_applet(&source, &info)));
+
+        let mut plan = match repaired {
+            Some((stable, actions)) => {
+                println!(
+                    "Rewrite emacsclient of {} to {}",
+                    bundle_path.display(),
+                    stable.display()
+                );
+                Plan::from(actions)
+            }
+            None => {
+                println!("emacsclient does not contain version, nothing to repair");
+                Plan::new()
+            }
+        };
+        plan.push_query(Path::new("osadecompile"), &args);
+        Ok(plan)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{
-        io::{Cursor, Read, Seek, SeekFrom},
-        path::PathBuf,
-    };
+    use std::fs;
+    use std::path::PathBuf;
+
+    use tempfile::TempDir;
+
+    use crate::command::RecordingCommandRunner;
+    use crate::execute;
 
     use super::*;
 
+    fn config_in(dir: &Path) -> MacOSConfig {
+        MacOSConfig {
+            emacsclient: EmacsclientOptions {
+                path: PathBuf::from("foo"),
+                ..EmacsclientOptions::default()
+            },
+            activate: Some(String::from("Emacs")),
+            applications_dir: dir.to_path_buf(),
+            bundle_name: String::from(bundle::BUNDLE_NAME),
+            bundle_identifier: String::from(bundle::BUNDLE_IDENTIFIER),
+            launch_services_plist: dir.join("secure.plist"),
+            background_only: false,
+            emacsclient_search: None,
+        }
+    }
+
+    fn installer_in(dir: &Path) -> MacOSRoamProtocolInstaller {
+        MacOSRoamProtocolInstaller::new(config_in(dir), Box::new(RecordingCommandRunner::default()))
+    }
+
+    // write Info.plist into the bundle as osacompile does, and return it after building
+    // applet
+    fn build_applet_with(installer: &MacOSRoamProtocolInstaller, original: &[u8]) -> Vec<u8> {
+        let path = installer.config.bundle_path().join("Contents/Info.plist");
+        let actions = installer.compile_applet("", &installer.applet_info(Vec::new()));
+        let mut runner = RecordingCommandRunner::default();
+
+        // write Info.plist instead of osacompile, then edit it
+        execute(&Plan::from(actions[..1].to_vec()), &mut runner).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, original).unwrap();
+        execute(&Plan::from(actions[1..].to_vec()), &mut runner).unwrap();
+        fs::read(path).unwrap()
+    }
+
     #[test]
-    fn write_protocol_script() {
+    fn compile_protocol_script_line_by_line() {
         // arrange
-        let installer = MacOSRoamProtocolInstaller::new(MacOSConfig {
-            emacsclient_path: PathBuf::from("foo"),
-        });
+        let installer = installer_in(Path::new("/Applications"));
+        let script = make_org_protocol_script(&installer.config);
 
         // do
-        let mut cursor = Cursor::new(Vec::new());
-        let _ = installer.write_protocol_script(&mut cursor);
+        let actions = installer.compile_applet(&script, &Dict::new());
 
         // verify
-        let mut buf = String::new();
-        cursor.seek(SeekFrom::Start(0)).unwrap();
-        cursor.read_to_string(&mut buf).unwrap();
-        assert_eq!(buf, make_org_protocol_script(Path::new("foo")))
+        let mut args = vec![
+            String::from("-o"),
+            String::from("/Applications/OrgProtocolClient.app"),
+        ];
+        for line in script.lines().skip(1) {
+            args.push(String::from("-e"));
+            args.push(String::from(line));
+        }
+        assert_eq!(
+            actions[0],
+            Action::RunCommand {
+                program: PathBuf::from("osacompile"),
+                args,
+                outputs: vec![PathBuf::from("/Applications/OrgProtocolClient.app")],
+                undo_args: None,
+            }
+        );
+    }
+
+    #[test]
+    fn pass_url_of_open_location_event_to_emacsclient() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut installer = installer_in(dir.path());
+
+        // do
+        let plan = installer.install().unwrap();
+
+        // verify
+        // URL comes as GURL Apple Event, that AppleScript receives with open location handler
+        let script = match &plan.actions()[0] {
+            Action::RunCommand { program, args, .. } if program == Path::new("osacompile") => args
+                .chunks(2)
+                .skip(1)
+                .map(|v| v[1].trim())
+                .collect::<Vec<_>>(),
+            action => panic!("unexpected action: {}", action),
+        };
+        assert_eq!(script[0], "on open location this_URL");
+        assert_eq!(script[2], "set filePath to quoted form of this_URL");
+        assert_eq!(script[3], "do shell script EC & filePath");
+        assert!(script[1].starts_with("set EC to \"'foo' '--no-wait'"));
+        assert_eq!(script.last(), Some(&"end open location"));
     }
 
     #[test]
     fn write_url_association_into_plist() {
         // arrange
-        let installer = MacOSRoamProtocolInstaller::new(MacOSConfig {
-            emacsclient_path: PathBuf::from("foo"),
-        });
-        let mut reader = BufReader::new(Cursor::new(Vec::from(
-            r#"<?xml version="1.0" encoding="utf-8"?><plist version="1.0"><dict></dict></plist>"#
-                .as_bytes(),
-        )));
+        let dir = TempDir::new().unwrap();
+        let installer = installer_in(dir.path());
+        let original =
+            r#"<?xml version="1.0" encoding="utf-8"?><plist version="1.0"><dict></dict></plist>"#;
 
         // do
-        let vec = installer.rewrite_plist(&mut reader);
+        let vec = build_applet_with(&installer, original.as_bytes());
 
         // verify
-        let plist_element = String::from(PLIST_ELEMENTS)
-            .lines()
-            .collect::<Vec<_>>()
-            .join("");
-        let expect = format!(
-            r#"<?xml version="1.0" encoding="utf-8"?><plist version="1.0"><dict>{}</dict></plist>"#,
-            plist_element
+        let mut expect = Dict::new();
+        expect.insert("CFBundleIdentifier", Value::from(bundle::BUNDLE_IDENTIFIER));
+        expect.insert(
+            "CFBundleURLTypes",
+            Value::Array(vec![org_protocol_url_type()]),
         );
         assert_eq!(
-            String::from_utf8(vec.unwrap()).unwrap().replace("\n", ""),
-            expect
+            plist::from_xml(&mut vec.as_slice()),
+            Ok(Value::Dict(expect))
+        );
+    }
+
+    #[test]
+    fn write_url_association_only_into_top_level_dict() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let installer = installer_in(dir.path());
+        let original = r#"<plist version="1.0"><dict><key>CFBundleName</key><string>Client</string><key>NSAppTransportSecurity</key><dict><key>NSAllowsArbitraryLoads</key><true/></dict></dict></plist>"#;
+
+        // do
+        let vec = build_applet_with(&installer, original.as_bytes());
+
+        // verify
+        let info = plist::from_xml(&mut vec.as_slice()).unwrap();
+        let info = info.as_dict().unwrap();
+        assert_eq!(
+            info.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec![
+                "CFBundleName",
+                "NSAppTransportSecurity",
+                "CFBundleIdentifier",
+                "CFBundleURLTypes"
+            ]
+        );
+        assert_eq!(
+            info.get("NSAppTransportSecurity")
+                .and_then(|v| v.as_dict())
+                .map(|v| v.len()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn write_binary_plist_back_in_binary() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let installer = installer_in(dir.path());
+        let mut info = Dict::new();
+        info.insert("CFBundleName", Value::from("Client"));
+        let original = plist::to_binary(&Value::Dict(info)).unwrap();
+
+        // do
+        let vec = build_applet_with(&installer, &original);
+
+        // verify
+        assert_eq!(plist::Format::detect(&vec), plist::Format::Binary);
+        let info = plist::from_binary(&vec).unwrap();
+        assert_eq!(
+            info.as_dict().and_then(|v| v.get("CFBundleURLTypes")),
+            Some(&Value::Array(vec![org_protocol_url_type()]))
+        );
+    }
+
+    #[test]
+    fn set_background_only_keys_into_plist() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut installer = installer_in(dir.path());
+        installer.config.background_only = true;
+        let original = r#"<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>org.orgroam.OrgProtocolClient</string><key>LSUIElement</key><false/></dict></plist>"#;
+
+        // do
+        let vec = build_applet_with(&installer, original.as_bytes());
+
+        // verify
+        let info = plist::from_xml(&mut vec.as_slice()).unwrap();
+        let info = info.as_dict().unwrap();
+        assert_eq!(
+            info.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec![
+                "CFBundleIdentifier",
+                "LSUIElement",
+                "LSBackgroundOnly",
+                "CFBundleURLTypes"
+            ]
+        );
+        assert_eq!(info.get("LSUIElement"), Some(&Value::Boolean(true)));
+        assert_eq!(info.get("LSBackgroundOnly"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn keep_existing_url_types_in_applet() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let installer = installer_in(dir.path());
+        let original = r#"<plist version="1.0"><dict><key>CFBundleURLTypes</key><array><dict><key>CFBundleURLSchemes</key><array><string>other</string></array></dict></array></dict></plist>"#;
+
+        // do
+        let vec = build_applet_with(&installer, original.as_bytes());
+
+        // verify
+        let info = plist::from_xml(&mut vec.as_slice()).unwrap();
+        let types = info
+            .as_dict()
+            .and_then(|v| v.get("CFBundleURLTypes"))
+            .and_then(|v| v.as_array())
+            .unwrap();
+        let mut other = Dict::new();
+        other.insert(
+            "CFBundleURLSchemes",
+            Value::Array(vec![Value::from("other")]),
+        );
+        assert_eq!(types, &vec![Value::Dict(other), org_protocol_url_type()]);
+    }
+
+    #[test]
+    fn merge_url_type_only_once() {
+        // arrange
+        let mut info = plist::from_xml_str(
+            r#"<plist version="1.0"><dict><key>CFBundleURLTypes</key><array><dict><key>CFBundleURLSchemes</key><array><string>other</string></array></dict></array></dict></plist>"#,
+        )
+        .unwrap();
+        let dict = info.as_dict_mut().unwrap();
+
+        // do
+        let first = add_url_type(dict);
+        let second = add_url_type(dict);
+
+        // verify
+        assert!(first);
+        assert!(!second);
+        let types = dict
+            .get("CFBundleURLTypes")
+            .and_then(|v| v.as_array())
+            .unwrap();
+        assert_eq!(types.len(), 2);
+        assert_eq!(types[1], org_protocol_url_type());
+    }
+
+    // bundle identifiers of LSHandlers in the preferences of LaunchServices
+    fn handlers_in(path: &Path) -> Vec<String> {
+        let (secure, _) = plist::from_bytes(&fs::read(path).unwrap()).unwrap();
+
+        secure
+            .as_dict()
+            .and_then(|v| v.get("LSHandlers"))
+            .and_then(|v| v.as_array())
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_dict()?.get("LSHandlerRoleAll")?.as_str())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn install_and_uninstall_bundle_in_configured_directory() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let mut config = config_in(&dir.path().join("Applications"));
+        config.bundle_name = String::from("Org Protocol");
+        config.bundle_identifier = String::from("org.example.OrgProtocol");
+        config.launch_services_plist = dir.path().join("secure.plist");
+        let bundle = config.bundle_path();
+        let runner = RecordingCommandRunner::default();
+        let mut installer = MacOSRoamProtocolInstaller::new(config, Box::new(runner.clone()));
+
+        // do
+        execute(&installer.install().unwrap(), &mut runner.clone()).unwrap();
+        let installed = bundle::has_identifier(&bundle, "org.example.OrgProtocol");
+        let registered = handlers_in(&dir.path().join("secure.plist"));
+        execute(&installer.uninstall().unwrap(), &mut runner.clone()).unwrap();
+
+        // verify
+        assert!(installed);
+        assert!(!bundle.exists());
+        assert_eq!(registered, vec![String::from("org.example.OrgProtocol")]);
+        assert_eq!(
+            handlers_in(&dir.path().join("secure.plist")),
+            Vec::<String>::new()
+        );
+        let bundle = bundle.to_string_lossy().to_string();
+        assert_eq!(runner.commands()[0][0], "osacompile");
+        assert_eq!(
+            runner.commands()[1..],
+            vec![
+                vec![
+                    String::from(launch_services::LSREGISTER),
+                    String::from("-f"),
+                    bundle.clone()
+                ],
+                vec![
+                    String::from(launch_services::LSREGISTER),
+                    String::from("-u"),
+                    bundle
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_replaced_handler_on_uninstall() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let config = config_in(dir.path());
+        let secure = config.launch_services_plist.clone();
+        let mut emacs = Dict::new();
+        emacs.insert("LSHandlerRoleAll", Value::from("org.gnu.emacs"));
+        emacs.insert("LSHandlerURLScheme", Value::from(ORG_PROTOCOL_SCHEME));
+        let mut original = Dict::new();
+        original.insert("LSHandlers", Value::Array(vec![Value::Dict(emacs)]));
+        fs::write(&secure, plist::to_xml(&Value::Dict(original))).unwrap();
+        let runner = RecordingCommandRunner::default();
+        let mut installer = MacOSRoamProtocolInstaller::new(config, Box::new(runner.clone()));
+
+        // do
+        execute(&installer.install().unwrap(), &mut runner.clone()).unwrap();
+        let installed = handlers_in(&secure);
+        execute(&installer.install().unwrap(), &mut runner.clone()).unwrap();
+        execute(&installer.uninstall().unwrap(), &mut runner.clone()).unwrap();
+
+        // verify
+        assert_eq!(installed, vec![String::from(bundle::BUNDLE_IDENTIFIER)]);
+        assert_eq!(handlers_in(&secure), vec![String::from("org.gnu.emacs")]);
+    }
+
+    // emacsclient in Cellar of Homebrew, and path of stable symlink to it
+    fn pinned_emacsclient_in(dir: &Path) -> (PathBuf, PathBuf) {
+        let prefix = dir.join("homebrew");
+        let pinned = prefix.join("Cellar/emacs-plus@29/29.1/bin/emacsclient");
+        fs::create_dir_all(pinned.parent().unwrap()).unwrap();
+        fs::write(&pinned, "").unwrap();
+        fs::create_dir_all(prefix.join("opt")).unwrap();
+        std::os::unix::fs::symlink(
+            prefix.join("Cellar/emacs-plus@29/29.1"),
+            prefix.join("opt/emacs-plus@29"),
+        )
+        .unwrap();
+
+        (pinned, prefix.join("opt/emacs-plus@29/bin/emacsclient"))
+    }
+
+    #[test]
+    fn keep_installed_keys_of_info_plist_on_repair() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let (pinned, _) = pinned_emacsclient_in(dir.path());
+        let mut installer = installer_in(&dir.path().join("Applications"));
+        installer.config.emacsclient.path = pinned;
+        installer.config.background_only = true;
+        let runner = RecordingCommandRunner::default();
+        execute(&installer.install().unwrap(), &mut runner.clone()).unwrap();
+        runner.set_output(
+            Path::new("osadecompile"),
+            Some(&make_org_protocol_script(&installer.config)),
+        );
+        installer.runner = Box::new(runner.clone());
+        installer.config.background_only = false;
+
+        // do
+        execute(&installer.repair().unwrap(), &mut runner.clone()).unwrap();
+
+        // verify
+        let info = bundle::read_info(&installer.config.bundle_path()).unwrap();
+        assert_eq!(
+            info.get("CFBundleIdentifier").and_then(|v| v.as_str()),
+            Some(bundle::BUNDLE_IDENTIFIER)
+        );
+        assert_eq!(info.get("LSUIElement"), Some(&Value::Boolean(true)));
+        assert_eq!(info.get("LSBackgroundOnly"), Some(&Value::Boolean(true)));
+        let compiled = runner
+            .commands()
+            .iter()
+            .filter(|v| v[0] == "osacompile")
+            .count();
+        assert_eq!(compiled, 2);
+    }
+
+    #[test]
+    fn repair_emacsclient_in_applescript() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let (pinned, stable) = pinned_emacsclient_in(dir.path());
+        let mut config = config_in(dir.path());
+        config.emacsclient.path = pinned;
+        let source = make_org_protocol_script(&config);
+
+        // do
+        let actual = repair_applescript(&source, None).unwrap();
+
+        // verify
+        config.emacsclient.path = stable.clone();
+        assert_eq!(actual, Some((stable, make_org_protocol_script(&config))));
+        assert_eq!(
+            repair_applescript(&make_org_protocol_script(&config), None).unwrap(),
+            None
         );
     }
 
     #[test]
     fn contains_emacsclient_path() {
         // arrange
-        let path = Path::new("foo/bar");
+        let mut config = config_in(Path::new("/Applications"));
+        config.emacsclient.path = PathBuf::from("foo/bar");
+
+        // do
+        let ret = make_org_protocol_script(&config);
+
+        // verify
+        assert!(ret.contains("foo/bar"))
+    }
+
+    #[test]
+    fn escape_emacsclient_command_in_applescript() {
+        // arrange
+        let mut config = config_in(Path::new("/Applications"));
+        config.emacsclient.path = PathBuf::from(r#"/opt/"emacs"\bin/emacsclient"#);
+        config.emacsclient.socket_name = Some(String::from("it's"));
+        config.activate = Some(String::from("Emacs-mac"));
+
+        // do
+        let ret = make_org_protocol_script(&config);
+
+        // verify
+        assert_eq!(
+            ret,
+            r#"
+on open location this_URL
+    set EC to "'/opt/\"emacs\"\\bin/emacsclient' '--no-wait' '--socket-name=it'\\''s' "
+    set filePath to quoted form of this_URL
+    do shell script EC & filePath
+    tell application "Emacs-mac" to activate
+end open location
+"#
+        );
+    }
+
+    #[test]
+    fn skip_activation_in_applescript() {
+        // arrange
+        let mut config = config_in(Path::new("/Applications"));
+        config.activate = None;
 
         // do
-        let ret = make_org_protocol_script(path);
+        let ret = make_org_protocol_script(&config);
 
         // verify
-        assert_eq!(ret.contains("foo/bar"), true)
+        assert!(!ret.contains("tell application"));
     }
 }
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::plan::Action;
+
+use super::plist::{self, Dict, Value};
+use super::InstallerResult;
+
+pub const BUNDLE_NAME: &str = "OrgProtocolClient";
+pub const BUNDLE_IDENTIFIER: &str = "org.orgroam.OrgProtocolClient";
+
+// Path of the bundle with the name in the applications directory. Both installer and
+// uninstaller find the bundle by this.
+pub fn bundle_path(applications_dir: &Path, name: &str) -> PathBuf {
+    applications_dir.join(format!("{}.app", name))
+}
+
+pub fn validate_bundle_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("Bundle name must not be empty");
+    }
+    if name.contains(['/', ':']) {
+        return Err("Bundle name must not contain '/' or ':'");
+    }
+
+    Ok(())
+}
+
+// CFBundleIdentifier is reverse DNS string, such as `org.orgroam.OrgProtocolClient`
+pub fn validate_bundle_identifier(identifier: &str) -> Result<(), &'static str> {
+    if identifier.is_empty() {
+        return Err("Bundle identifier must not be empty");
+    }
+    if !identifier
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err("Bundle identifier must contain only alphanumerics, hyphens and periods");
+    }
+
+    Ok(())
+}
+
+// Top level dict of Info.plist of the bundle, or None if it can not be read
+pub fn read_info(bundle: &Path) -> Option<Dict> {
+    let content = fs::read(bundle.join("Contents/Info.plist")).ok()?;
+
+    match plist::from_bytes(&content).ok()? {
+        (Value::Dict(info), _) => Some(info),
+        _ => None,
+    }
+}
+
+// Return true if the bundle has the identifier, that is, the bundle is written by this installer.
+pub fn has_identifier(bundle: &Path, identifier: &str) -> bool {
+    read_info(bundle)
+        .is_some_and(|v| v.get("CFBundleIdentifier").and_then(|v| v.as_str()) == Some(identifier))
+}
+
+// Action to remove the bundle recursively. Bundle not written by this installer is refused
+// to remove. Return None if the bundle does not exist.
+pub fn removal(bundle: &Path, identifier: &str) -> InstallerResult<Option<Action>> {
+    if fs::symlink_metadata(bundle).is_err() {
+        return Ok(None);
+    }
+    if !has_identifier(bundle, identifier) {
+        return Err(format!(
+            "{} does not have bundle identifier {}, so it is not created by this installer. Remove it by hand if needed",
+            bundle.display(),
+            identifier
+        )
+        .into());
+    }
+
+    Ok(Some(Action::RemoveDir(bundle.to_path_buf())))
+}
+
+// quote the string for POSIX shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r#"'\''"#))
+}
+
+// Split the first word from the command line written with shell_quote. Return the word
+// unquoted, and the rest of the command line after it.
+pub fn split_shell_word(command: &str) -> Option<(String, &str)> {
+    let command = command.trim_start();
+    let mut word = String::new();
+    let mut chars = command.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => loop {
+                match chars.next()? {
+                    (_, '\'') => break,
+                    (_, c) => word.push(c),
+                }
+            },
+            '\\' => word.push(chars.next()?.1),
+            c if c.is_whitespace() => return Some((word, &command[i..])),
+            c => word.push(c),
+        }
+    }
+
+    if word.is_empty() {
+        None
+    } else {
+        Some((word, ""))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    // write bundle with Info.plist that has the identifier
+    fn write(bundle: &Path, identifier: &str) {
+        let mut info = Dict::new();
+        info.insert("CFBundleIdentifier", Value::from(identifier));
+        fs::create_dir_all(bundle.join("Contents")).unwrap();
+        fs::write(
+            bundle.join("Contents/Info.plist"),
+            plist::to_xml(&Value::Dict(info)),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn remove_only_own_bundle() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let other = bundle_path(dir.path(), "OrgProtocolClient");
+        write(&other, "org.example.other");
+        let own = bundle_path(dir.path(), "Own");
+        write(&own, BUNDLE_IDENTIFIER);
+
+        // do
+        let removed_other = removal(&other, BUNDLE_IDENTIFIER);
+        let removed_own = removal(&own, BUNDLE_IDENTIFIER);
+        let removed_missing = removal(&dir.path().join("Missing.app"), BUNDLE_IDENTIFIER);
+
+        // verify
+        assert!(removed_other.is_err());
+        assert_eq!(removed_own.unwrap(), Some(Action::RemoveDir(own)));
+        assert_eq!(removed_missing.unwrap(), None);
+    }
+
+    #[test]
+    fn split_first_word_quoted_for_shell() {
+        // arrange
+        let command = format!(
+            "{} '--no-wait' \"$@\"",
+            shell_quote("/opt/it's/emacsclient")
+        );
+
+        // do
+        let actual = split_shell_word(&command);
+
+        // verify
+        assert_eq!(
+            actual,
+            Some((String::from("/opt/it's/emacsclient"), " '--no-wait' \"$@\""))
+        );
+        assert_eq!(
+            split_shell_word("/usr/bin/emacsclient --no-wait "),
+            Some((String::from("/usr/bin/emacsclient"), " --no-wait "))
+        );
+        assert_eq!(split_shell_word("'unterminated"), None);
+    }
+}
@@ -0,0 +1,320 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::command::CommandRunner;
+
+use super::InstallerResult;
+
+// directories that have emacsclient, in order of preference after PATH
+const EMACS_APP: &str = "Applications/Emacs.app/Contents/MacOS";
+const HOMEBREW_BIN_DIRS: &[&str] = &["opt/homebrew/bin", "usr/local/bin"];
+const MACPORTS_BIN_DIRS: &[&str] = &["opt/local/bin"];
+const NIX_BIN_DIRS: &[&str] = &[
+    "run/current-system/sw/bin",
+    "nix/var/nix/profiles/default/bin",
+];
+// relative to home directory
+const NIX_PROFILE_BIN_DIR: &str = ".nix-profile/bin";
+
+// Where to search emacsclient. All directories are resolved under the root, to search
+// in a fake filesystem in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Search {
+    pub root: PathBuf,
+    pub home: Option<PathBuf>,
+    // directories in PATH
+    pub path: Vec<PathBuf>,
+}
+
+impl Search {
+    pub fn from_env() -> Self {
+        Search {
+            root: PathBuf::from("/"),
+            home: dirs::home_dir(),
+            path: env::var_os("PATH")
+                .map(|v| env::split_paths(&v).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    // directories to search in order of preference. Directories in PATH are preferred,
+    // because it is what user runs in terminal.
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut dirs = self
+            .path
+            .iter()
+            .map(|v| self.resolve(v))
+            .collect::<Vec<_>>();
+
+        dirs.extend(emacs_app_bin_dirs(&self.root.join(EMACS_APP)));
+        dirs.extend(HOMEBREW_BIN_DIRS.iter().map(|v| self.root.join(v)));
+        dirs.extend(MACPORTS_BIN_DIRS.iter().map(|v| self.root.join(v)));
+        if let Some(home) = &self.home {
+            dirs.push(self.resolve(home).join(NIX_PROFILE_BIN_DIR));
+        }
+        dirs.extend(NIX_BIN_DIRS.iter().map(|v| self.root.join(v)));
+
+        dirs
+    }
+
+    // Executable emacsclients found, in order of preference. The same file found via
+    // symlinks is listed once.
+    pub fn candidates(&self) -> Vec<PathBuf> {
+        let mut seen = Vec::new();
+        let mut candidates = Vec::new();
+
+        for dir in self.directories() {
+            let path = dir.join("emacsclient");
+            if !is_executable(&path) {
+                continue;
+            }
+
+            let real = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if seen.contains(&real) {
+                continue;
+            }
+            seen.push(real);
+            candidates.push(path);
+        }
+
+        candidates
+    }
+}
+
+// Emacs for Mac OS X has `bin` and also `bin-<arch>-<os version>` directories
+fn emacs_app_bin_dirs(macos_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = match fs::read_dir(macos_dir) {
+        Ok(entries) => entries
+            .filter_map(|v| v.ok())
+            .map(|v| v.path())
+            .filter(|v| {
+                v.file_name()
+                    .and_then(|v| v.to_str())
+                    .is_some_and(|v| v.starts_with("bin"))
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => return Vec::new(),
+    };
+    dirs.sort_by_key(|v| (v.file_name() != Some("bin".as_ref()), v.clone()));
+
+    dirs
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|v| v.is_file() && v.permissions().mode() & 0o111 != 0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emacsclient {
+    pub path: PathBuf,
+    // first line of `emacsclient --version`, such as `emacsclient 29.1`
+    pub version: String,
+}
+
+// Numbers of version in output of --version, such as [29, 1] for `emacsclient 29.1`. Empty if
+// no version is found, that is ranked lowest.
+fn version_numbers(version: &str) -> Vec<u32> {
+    version
+        .split_whitespace()
+        .find(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|v| v.split('.').map_while(|n| n.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+// Run all candidates with --version, and use the newest one. Candidates of the same version
+// are ranked in order of preference.
+pub fn detect(search: &Search, runner: &mut dyn CommandRunner) -> InstallerResult<Emacsclient> {
+    let candidates = search.candidates();
+    let mut best: Option<(Vec<u32>, Emacsclient)> = None;
+
+    for path in &candidates {
+        let output = match runner.run(path, &[String::from("--version")]) {
+            Ok(output) => output,
+            Err(e) => {
+                println!("Skip {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let version = String::from(output.lines().next().unwrap_or("").trim());
+        println!("Found {} ({})", path.display(), version);
+
+        let numbers = version_numbers(&version);
+        if best.as_ref().map_or(true, |(v, _)| numbers > *v) {
+            best = Some((
+                numbers,
+                Emacsclient {
+                    path: path.clone(),
+                    version,
+                },
+            ));
+        }
+    }
+
+    match best {
+        Some((_, emacsclient)) => Ok(emacsclient),
+        None if candidates.is_empty() => {
+            Err("Can not find emacsclient. Specify it with --emacsclient-path".into())
+        }
+        None => Err("None of emacsclient found can run. Specify it with --emacsclient-path".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use crate::command::RecordingCommandRunner;
+
+    use super::*;
+
+    fn write_executable(path: &Path, mode: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn rank_candidates_in_known_locations() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write_executable(&root.join("Users/foo/bin/emacsclient"), 0o755);
+        write_executable(
+            &root.join("Applications/Emacs.app/Contents/MacOS/bin-x86_64-10_14/emacsclient"),
+            0o755,
+        );
+        write_executable(
+            &root.join("Applications/Emacs.app/Contents/MacOS/bin/emacsclient"),
+            0o755,
+        );
+        write_executable(
+            &root.join("opt/homebrew/Cellar/emacs/29.1/bin/emacsclient"),
+            0o755,
+        );
+        fs::create_dir_all(root.join("opt/homebrew/bin")).unwrap();
+        symlink(
+            root.join("opt/homebrew/Cellar/emacs/29.1/bin/emacsclient"),
+            root.join("opt/homebrew/bin/emacsclient"),
+        )
+        .unwrap();
+        write_executable(&root.join("opt/local/bin/emacsclient"), 0o644);
+        write_executable(&root.join("Users/foo/.nix-profile/bin/emacsclient"), 0o755);
+        let search = Search {
+            root: root.to_path_buf(),
+            home: Some(PathBuf::from("/Users/foo")),
+            path: vec![
+                PathBuf::from("/opt/homebrew/bin"),
+                PathBuf::from("/Users/foo/bin"),
+            ],
+        };
+
+        // do
+        let actual = search.candidates();
+
+        // verify
+        assert_eq!(
+            actual,
+            vec![
+                root.join("opt/homebrew/bin/emacsclient"),
+                root.join("Users/foo/bin/emacsclient"),
+                root.join("Applications/Emacs.app/Contents/MacOS/bin/emacsclient"),
+                root.join("Applications/Emacs.app/Contents/MacOS/bin-x86_64-10_14/emacsclient"),
+                root.join("Users/foo/.nix-profile/bin/emacsclient"),
+            ]
+        );
+    }
+
+    #[test]
+    fn use_first_candidate_that_runs() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write_executable(&root.join("usr/local/bin/emacsclient"), 0o755);
+        write_executable(&root.join("opt/local/bin/emacsclient"), 0o755);
+        let search = Search {
+            root: root.to_path_buf(),
+            home: None,
+            path: Vec::new(),
+        };
+        let mut runner = RecordingCommandRunner::default();
+        runner.set_output(&root.join("usr/local/bin/emacsclient"), None);
+        runner.set_output(
+            &root.join("opt/local/bin/emacsclient"),
+            Some("emacsclient 29.1\n"),
+        );
+
+        // do
+        let actual = detect(&search, &mut runner);
+
+        // verify
+        assert_eq!(
+            actual.unwrap(),
+            Emacsclient {
+                path: root.join("opt/local/bin/emacsclient"),
+                version: String::from("emacsclient 29.1"),
+            }
+        );
+    }
+
+    #[test]
+    fn use_newest_version_of_candidates() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for bin in &["usr/bin", "usr/local/bin", "opt/local/bin"] {
+            write_executable(&root.join(bin).join("emacsclient"), 0o755);
+        }
+        let search = Search {
+            root: root.to_path_buf(),
+            home: None,
+            path: vec![PathBuf::from("/usr/bin")],
+        };
+        let mut runner = RecordingCommandRunner::default();
+        runner.set_output(
+            &root.join("usr/bin/emacsclient"),
+            Some("emacsclient 26.3\n"),
+        );
+        runner.set_output(
+            &root.join("usr/local/bin/emacsclient"),
+            Some("emacsclient 29.1\n"),
+        );
+        runner.set_output(
+            &root.join("opt/local/bin/emacsclient"),
+            Some("emacsclient 29.1\n"),
+        );
+
+        // do
+        let actual = detect(&search, &mut runner);
+
+        // verify
+        assert_eq!(actual.unwrap().path, root.join("usr/local/bin/emacsclient"));
+        assert_eq!(runner.commands().len(), 3);
+        assert_eq!(version_numbers("emacsclient 30.0.50"), vec![30, 0, 50]);
+        assert_eq!(version_numbers("emacsclient"), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn get_error_if_no_emacsclient() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let search = Search {
+            root: dir.path().to_path_buf(),
+            home: None,
+            path: vec![PathBuf::from("/usr/bin")],
+        };
+
+        // do
+        let actual = detect(&search, &mut RecordingCommandRunner::default());
+
+        // verify
+        assert!(actual.is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::plan::Action;
+
+use super::plist::{self, Dict, Value};
+use super::InstallerResult;
+
+pub const LSREGISTER: &str = "/System/Library/Frameworks/CoreServices.framework/Frameworks/LaunchServices.framework/Support/lsregister";
+
+// preferences of LaunchServices, relative to home directory
+pub const SECURE_PLIST: &str =
+    "Library/Preferences/com.apple.LaunchServices/com.apple.launchservices.secure.plist";
+
+const LS_HANDLERS: &str = "LSHandlers";
+const LS_HANDLER_URL_SCHEME: &str = "LSHandlerURLScheme";
+const LS_HANDLER_ROLE_ALL: &str = "LSHandlerRoleAll";
+const LS_HANDLER_PREFERRED_VERSIONS: &str = "LSHandlerPreferredVersions";
+
+// key in Info.plist of the bundle, to record handlers of the scheme replaced by the bundle
+pub const PREVIOUS_HANDLERS_KEY: &str = "OrgRoamProtocolPreviousHandlers";
+
+pub fn secure_plist_path(home: &Path) -> PathBuf {
+    home.join(SECURE_PLIST)
+}
+
+// register the bundle to LaunchServices database, to make its URL types known. It is
+// unregistered on failure.
+pub fn register(bundle: &Path) -> Action {
+    lsregister("-f", "-u", bundle)
+}
+
+pub fn unregister(bundle: &Path) -> Action {
+    lsregister("-u", "-f", bundle)
+}
+
+fn lsregister(flag: &str, undo_flag: &str, bundle: &Path) -> Action {
+    let bundle = bundle.to_string_lossy().to_string();
+    Action::RunCommand {
+        program: PathBuf::from(LSREGISTER),
+        args: vec![String::from(flag), bundle.clone()],
+        outputs: Vec::new(),
+        undo_args: Some(vec![String::from(undo_flag), bundle]),
+    }
+}
+
+fn handles_scheme(handler: &Value, scheme: &str) -> bool {
+    handler
+        .as_dict()
+        .and_then(|v| v.get(LS_HANDLER_URL_SCHEME))
+        .and_then(|v| v.as_str())
+        .is_some_and(|v| v.eq_ignore_ascii_case(scheme))
+}
+
+fn is_handler(handler: &Value, identifier: &str) -> bool {
+    handler
+        .as_dict()
+        .and_then(|v| v.get(LS_HANDLER_ROLE_ALL))
+        .and_then(|v| v.as_str())
+        .is_some_and(|v| v.eq_ignore_ascii_case(identifier))
+}
+
+fn handler_entry(scheme: &str, identifier: &str) -> Value {
+    let mut versions = Dict::new();
+    versions.insert(LS_HANDLER_ROLE_ALL, Value::from("-"));
+    let mut handler = Dict::new();
+    handler.insert(LS_HANDLER_PREFERRED_VERSIONS, Value::Dict(versions));
+    handler.insert(LS_HANDLER_ROLE_ALL, Value::from(identifier));
+    handler.insert(LS_HANDLER_URL_SCHEME, Value::from(scheme));
+
+    Value::Dict(handler)
+}
+
+// Make the bundle default handler of the URL scheme in LSHandlers. Handler of other scheme
+// is kept. Return true if changed.
+pub fn set_default_handler(secure: &mut Dict, scheme: &str, identifier: &str) -> bool {
+    let entry = handler_entry(scheme, identifier);
+    let handlers = match secure.get_mut(LS_HANDLERS).and_then(|v| v.as_array_mut()) {
+        Some(handlers) => handlers,
+        None => {
+            secure.insert(LS_HANDLERS, Value::Array(vec![entry]));
+            return true;
+        }
+    };
+
+    let current = handlers
+        .iter()
+        .filter(|v| handles_scheme(v, scheme))
+        .collect::<Vec<_>>();
+    if current.len() == 1 && *current[0] == entry {
+        return false;
+    }
+
+    handlers.retain(|v| !handles_scheme(v, scheme));
+    handlers.push(entry);
+    true
+}
+
+// Handlers of the URL scheme other than the bundle, to be replaced by it
+pub fn other_handlers(secure: &Dict, scheme: &str, identifier: &str) -> Vec<Value> {
+    secure
+        .get(LS_HANDLERS)
+        .and_then(|v| v.as_array())
+        .map(|handlers| {
+            handlers
+                .iter()
+                .filter(|v| handles_scheme(v, scheme) && !is_handler(v, identifier))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Remove the handler of the URL scheme, only if it is the bundle, and put back the handlers
+// replaced by it. Return true if changed.
+pub fn remove_default_handler(
+    secure: &mut Dict,
+    scheme: &str,
+    identifier: &str,
+    previous: &[Value],
+) -> bool {
+    let handlers = match secure.get_mut(LS_HANDLERS).and_then(|v| v.as_array_mut()) {
+        Some(handlers) => handlers,
+        None => return false,
+    };
+
+    let len = handlers.len();
+    handlers.retain(|v| !(handles_scheme(v, scheme) && is_handler(v, identifier)));
+    if handlers.len() == len {
+        return false;
+    }
+
+    if !handlers.iter().any(|v| handles_scheme(v, scheme)) {
+        handlers.extend(
+            previous
+                .iter()
+                .filter(|v| handles_scheme(v, scheme))
+                .cloned(),
+        );
+    }
+    true
+}
+
+// Read the preferences of LaunchServices, or empty dict if the file does not exist
+pub fn read_secure_plist(path: &Path) -> InstallerResult<Dict> {
+    let secure = match fs::read(path) {
+        Ok(content) => plist::from_bytes(&content)?.0,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Dict::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    match secure {
+        Value::Dict(secure) => Ok(secure),
+        _ => Err(format!("Top level of {} is not dict", path.display()).into()),
+    }
+}
+
+// Actions to edit the preferences of LaunchServices. Return no action if the edit does not
+// change anything.
+pub fn edit_secure_plist(
+    path: &Path,
+    edit: impl FnOnce(&mut Dict) -> bool,
+) -> InstallerResult<Vec<Action>> {
+    let original = read_secure_plist(path)?;
+
+    let mut edited = original.clone();
+    if !edit(&mut edited) {
+        return Ok(Vec::new());
+    }
+    Ok(Action::plist_edits(path, &original, &edited))
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use crate::command::RecordingCommandRunner;
+    use crate::execute;
+    use crate::plan::Plan;
+
+    use super::plist::Format;
+    use super::*;
+
+    const FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>LSHandlers</key>
+	<array>
+		<dict>
+			<key>LSHandlerPreferredVersions</key>
+			<dict>
+				<key>LSHandlerRoleAll</key>
+				<string>-</string>
+			</dict>
+			<key>LSHandlerRoleAll</key>
+			<string>com.apple.safari</string>
+			<key>LSHandlerURLScheme</key>
+			<string>http</string>
+		</dict>
+		<dict>
+			<key>LSHandlerRoleAll</key>
+			<string>org.gnu.emacs</string>
+			<key>LSHandlerURLScheme</key>
+			<string>org-protocol</string>
+		</dict>
+	</array>
+	<key>LSHandlersVersion</key>
+	<integer>1</integer>
+</dict>
+</plist>
+"#;
+
+    // edit the preferences, and return true if changed
+    fn edit(path: &Path, edit: impl FnOnce(&mut Dict) -> bool) -> bool {
+        let actions = edit_secure_plist(path, edit).unwrap();
+        let changed = !actions.is_empty();
+        execute(&Plan::from(actions), &mut RecordingCommandRunner::default()).unwrap();
+
+        changed
+    }
+
+    fn schemes(path: &Path) -> Vec<(String, String)> {
+        let (secure, _) = plist::from_bytes(&fs::read(path).unwrap()).unwrap();
+
+        secure
+            .as_dict()
+            .and_then(|v| v.get(LS_HANDLERS))
+            .and_then(|v| v.as_array())
+            .unwrap()
+            .iter()
+            .map(|v| {
+                let v = v.as_dict().unwrap();
+                (
+                    String::from(v.get(LS_HANDLER_URL_SCHEME).unwrap().as_str().unwrap()),
+                    String::from(v.get(LS_HANDLER_ROLE_ALL).unwrap().as_str().unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replace_handler_of_scheme() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = secure_plist_path(dir.path());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, FIXTURE).unwrap();
+
+        // do
+        let changed = edit(&path, |v| {
+            set_default_handler(v, "org-protocol", "org.example.client")
+        });
+        let changed_again = edit(&path, |v| {
+            set_default_handler(v, "org-protocol", "org.example.client")
+        });
+
+        // verify
+        assert!(changed);
+        assert!(!changed_again);
+        assert_eq!(
+            schemes(&path),
+            vec![
+                (String::from("http"), String::from("com.apple.safari")),
+                (
+                    String::from("org-protocol"),
+                    String::from("org.example.client")
+                )
+            ]
+        );
+        assert_eq!(Format::detect(&fs::read(&path).unwrap()), Format::Xml);
+    }
+
+    #[test]
+    fn create_binary_preferences_if_missing() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = secure_plist_path(dir.path());
+
+        // do
+        edit(&path, |v| {
+            set_default_handler(v, "org-protocol", "org.example.client")
+        });
+
+        // verify
+        assert_eq!(Format::detect(&fs::read(&path).unwrap()), Format::Binary);
+        assert_eq!(
+            schemes(&path),
+            vec![(
+                String::from("org-protocol"),
+                String::from("org.example.client")
+            )]
+        );
+    }
+
+    #[test]
+    fn remove_only_own_handler() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secure.plist");
+        fs::write(&path, FIXTURE).unwrap();
+
+        // do
+        let removed_other = edit(&path, |v| {
+            remove_default_handler(v, "org-protocol", "org.example.client", &[])
+        });
+        let removed_own = edit(&path, |v| {
+            remove_default_handler(v, "org-protocol", "org.gnu.emacs", &[])
+        });
+
+        // verify
+        assert!(!removed_other);
+        assert!(removed_own);
+        assert_eq!(
+            schemes(&path),
+            vec![(String::from("http"), String::from("com.apple.safari"))]
+        );
+    }
+
+    #[test]
+    fn put_back_replaced_handler_on_removal() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secure.plist");
+        fs::write(&path, FIXTURE).unwrap();
+        let previous = other_handlers(
+            &read_secure_plist(&path).unwrap(),
+            "org-protocol",
+            "org.example.client",
+        );
+
+        // do
+        edit(&path, |v| {
+            set_default_handler(v, "org-protocol", "org.example.client")
+        });
+        edit(&path, |v| {
+            remove_default_handler(v, "org-protocol", "org.example.client", &previous)
+        });
+
+        // verify
+        assert_eq!(previous.len(), 1);
+        assert_eq!(
+            schemes(&path),
+            vec![
+                (String::from("http"), String::from("com.apple.safari")),
+                (String::from("org-protocol"), String::from("org.gnu.emacs"))
+            ]
+        );
+    }
+
+    #[test]
+    fn register_bundle_with_lsregister() {
+        // arrange
+        let mut runner = RecordingCommandRunner::default();
+
+        // do
+        let action = register(Path::new("/Applications/OrgProtocolClient.app"));
+        execute(&Plan::from(vec![action]), &mut runner).unwrap();
+
+        // verify
+        assert_eq!(
+            runner.commands(),
+            vec![vec![
+                String::from(LSREGISTER),
+                String::from("-f"),
+                String::from("/Applications/OrgProtocolClient.app")
+            ]]
+        );
+    }
+}
@@ -0,0 +1,571 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io::BufRead;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+mod binary;
+
+pub use binary::{from_binary, to_binary};
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+"#;
+
+// Value in property list
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Dict(Dict),
+    Array(Vec<Value>),
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    // ISO 8601 string, such as `2001-01-01T00:00:00Z`. Fraction of second, that only binary
+    // plist can have, is kept as `2001-01-01T00:00:00.5Z`.
+    Date(String),
+    Data(Vec<u8>),
+    // reference to object in keyed archive, that only binary plist can have
+    Uid(u64),
+}
+
+impl Value {
+    pub fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict_mut(&mut self) -> Option<&mut Dict> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match self {
+            Value::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(String::from(value))
+    }
+}
+
+impl From<Dict> for Value {
+    fn from(value: Dict) -> Self {
+        Value::Dict(value)
+    }
+}
+
+// Dictionary that keeps order of keys, to write back the file with minimum changes
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dict {
+    entries: Vec<(String, Value)>,
+}
+
+impl Dict {
+    pub fn new() -> Self {
+        Dict::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    // Insert the value. Value for the key already exists is replaced at the same position,
+    // and returned.
+    pub fn insert(&mut self, key: &str, value: Value) -> Option<Value> {
+        match self.get_mut(key) {
+            Some(v) => Some(std::mem::replace(v, value)),
+            None => {
+                self.entries.push((String::from(key), value));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PlistError {
+    message: String,
+}
+
+impl PlistError {
+    pub fn new(message: &str) -> Self {
+        PlistError {
+            message: String::from(message),
+        }
+    }
+}
+
+impl Display for PlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid plist: {}", self.message)
+    }
+}
+
+impl Error for PlistError {}
+
+enum Token {
+    Start(String),
+    Empty(String),
+    End(String),
+    Text(String),
+    Eof,
+}
+
+struct XmlParser<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> XmlParser<R> {
+    fn next(&mut self) -> Result<Token, PlistError> {
+        loop {
+            self.buf.clear();
+            let token = match self.reader.read_event(&mut self.buf) {
+                Ok(Event::Start(e)) => Token::Start(String::from_utf8_lossy(e.name()).to_string()),
+                Ok(Event::Empty(e)) => Token::Empty(String::from_utf8_lossy(e.name()).to_string()),
+                Ok(Event::End(e)) => Token::End(String::from_utf8_lossy(e.name()).to_string()),
+                Ok(Event::Text(e)) => Token::Text(
+                    e.unescape_and_decode(&self.reader)
+                        .map_err(|e| PlistError::new(&e.to_string()))?,
+                ),
+                Ok(Event::CData(e)) => Token::Text(String::from_utf8_lossy(&e).to_string()),
+                Ok(Event::Eof) => Token::Eof,
+                Ok(_) => continue,
+                Err(e) => return Err(PlistError::new(&e.to_string())),
+            };
+
+            return Ok(token);
+        }
+    }
+
+    // next token other than whitespaces between elements
+    fn next_element(&mut self) -> Result<Token, PlistError> {
+        loop {
+            match self.next()? {
+                Token::Text(text) if text.trim().is_empty() => continue,
+                token => return Ok(token),
+            }
+        }
+    }
+
+    fn read_text(&mut self, name: &str) -> Result<String, PlistError> {
+        let mut text = String::new();
+
+        loop {
+            match self.next()? {
+                Token::Text(t) => text.push_str(&t),
+                Token::End(n) if n == name => return Ok(text),
+                _ => {
+                    return Err(PlistError::new(&format!(
+                        "unexpected element in <{}>",
+                        name
+                    )))
+                }
+            }
+        }
+    }
+
+    fn parse_value(&mut self, token: Token) -> Result<Value, PlistError> {
+        match token {
+            Token::Start(name) => match name.as_str() {
+                "dict" => self.parse_dict(),
+                "array" => self.parse_array(),
+                "string" => Ok(Value::String(self.read_text("string")?)),
+                "integer" => {
+                    let text = self.read_text("integer")?;
+                    text.trim()
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| PlistError::new(&format!("invalid integer: {}", text)))
+                }
+                "real" => {
+                    let text = self.read_text("real")?;
+                    text.trim()
+                        .parse()
+                        .map(Value::Real)
+                        .map_err(|_| PlistError::new(&format!("invalid real: {}", text)))
+                }
+                "date" => Ok(Value::Date(String::from(self.read_text("date")?.trim()))),
+                "data" => decode_base64(&self.read_text("data")?)
+                    .map(Value::Data)
+                    .ok_or_else(|| PlistError::new("invalid base64 in <data>")),
+                "true" | "false" => {
+                    self.read_text(&name)?;
+                    Ok(Value::Boolean(name == "true"))
+                }
+                _ => Err(PlistError::new(&format!("unknown element <{}>", name))),
+            },
+            Token::Empty(name) => match name.as_str() {
+                "dict" => Ok(Value::Dict(Dict::new())),
+                "array" => Ok(Value::Array(Vec::new())),
+                "string" => Ok(Value::String(String::new())),
+                "data" => Ok(Value::Data(Vec::new())),
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                _ => Err(PlistError::new(&format!("unexpected element <{}/>", name))),
+            },
+            Token::End(name) => Err(PlistError::new(&format!("unexpected </{}>", name))),
+            Token::Text(_) => Err(PlistError::new("unexpected text")),
+            Token::Eof => Err(PlistError::new("unexpected end of file")),
+        }
+    }
+
+    fn parse_dict(&mut self) -> Result<Value, PlistError> {
+        let mut dict = Dict::new();
+
+        loop {
+            let key = match self.next_element()? {
+                Token::End(name) if name == "dict" => return Ok(Value::Dict(dict)),
+                Token::Start(name) if name == "key" => self.read_text("key")?,
+                Token::Empty(name) if name == "key" => String::new(),
+                _ => return Err(PlistError::new("<key> is expected in <dict>")),
+            };
+            let token = self.next_element()?;
+            let value = self.parse_value(token)?;
+
+            dict.insert(&key, value);
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, PlistError> {
+        let mut array = Vec::new();
+
+        loop {
+            match self.next_element()? {
+                Token::End(name) if name == "array" => return Ok(Value::Array(array)),
+                token => array.push(self.parse_value(token)?),
+            }
+        }
+    }
+}
+
+// Format of property list file, to write it back in the format it was read in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Xml,
+    Binary,
+}
+
+impl Format {
+    pub fn detect(content: &[u8]) -> Format {
+        if content.starts_with(binary::BINARY_MAGIC) {
+            Format::Binary
+        } else {
+            Format::Xml
+        }
+    }
+}
+
+// parse property list in either format, and return the format detected
+pub fn from_bytes(content: &[u8]) -> Result<(Value, Format), PlistError> {
+    let format = Format::detect(content);
+    let value = match format {
+        Format::Xml => from_xml(&mut &content[..])?,
+        Format::Binary => from_binary(content)?,
+    };
+
+    Ok((value, format))
+}
+
+pub fn to_bytes(value: &Value, format: Format) -> Result<Vec<u8>, PlistError> {
+    match format {
+        Format::Xml => Ok(to_xml(value).into_bytes()),
+        Format::Binary => to_binary(value),
+    }
+}
+
+// parse XML property list
+pub fn from_xml(reader: &mut dyn BufRead) -> Result<Value, PlistError> {
+    let mut parser = XmlParser {
+        reader: Reader::from_reader(reader),
+        buf: Vec::new(),
+    };
+
+    match parser.next_element()? {
+        Token::Start(name) if name == "plist" => (),
+        _ => return Err(PlistError::new("<plist> is expected")),
+    }
+    let token = parser.next_element()?;
+    let value = parser.parse_value(token)?;
+
+    match parser.next_element()? {
+        Token::End(name) if name == "plist" => Ok(value),
+        _ => Err(PlistError::new("</plist> is expected")),
+    }
+}
+
+pub fn from_xml_str(content: &str) -> Result<Value, PlistError> {
+    from_xml(&mut content.as_bytes())
+}
+
+// serialize property list in the format Xcode writes
+pub fn to_xml(value: &Value) -> String {
+    let mut out = String::from(XML_HEADER);
+
+    out.push_str("<plist version=\"1.0\">\n");
+    write_value(&mut out, value, 0);
+    out.push_str("</plist>\n");
+
+    out
+}
+
+fn write_value(out: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth);
+
+    match value {
+        Value::Dict(dict) if dict.is_empty() => out.push_str(&format!("{}<dict/>\n", indent)),
+        Value::Dict(dict) => {
+            out.push_str(&format!("{}<dict>\n", indent));
+            for (key, value) in dict.iter() {
+                out.push_str(&format!("{}\t<key>{}</key>\n", indent, escape(key)));
+                write_value(out, value, depth + 1);
+            }
+            out.push_str(&format!("{}</dict>\n", indent));
+        }
+        Value::Array(array) if array.is_empty() => out.push_str(&format!("{}<array/>\n", indent)),
+        Value::Array(array) => {
+            out.push_str(&format!("{}<array>\n", indent));
+            for value in array {
+                write_value(out, value, depth + 1);
+            }
+            out.push_str(&format!("{}</array>\n", indent));
+        }
+        Value::String(value) => {
+            out.push_str(&format!("{}<string>{}</string>\n", indent, escape(value)))
+        }
+        Value::Integer(value) => out.push_str(&format!("{}<integer>{}</integer>\n", indent, value)),
+        Value::Real(value) => out.push_str(&format!("{}<real>{}</real>\n", indent, value)),
+        Value::Boolean(true) => out.push_str(&format!("{}<true/>\n", indent)),
+        Value::Boolean(false) => out.push_str(&format!("{}<false/>\n", indent)),
+        Value::Date(value) => out.push_str(&format!("{}<date>{}</date>\n", indent, value)),
+        Value::Data(value) => out.push_str(&format!(
+            "{}<data>{}</data>\n",
+            indent,
+            encode_base64(value)
+        )),
+        // written as plutil does
+        Value::Uid(value) => {
+            let mut dict = Dict::new();
+            dict.insert("CF$UID", Value::Integer(*value as i64));
+            write_value(out, &Value::Dict(dict), depth);
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::new();
+
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut n: u32 = 0;
+    let mut bits = 0;
+
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = BASE64_CHARS.iter().position(|v| *v == c)? as u32;
+        n = (n << 6) | value;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_serialize_all_types() {
+        // arrange
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Array</key>
+	<array>
+		<string> a &amp; b </string>
+		<integer>-42</integer>
+		<real>1.5</real>
+	</array>
+	<key>Date</key>
+	<date>2001-01-01T00:00:00Z</date>
+	<key>Data</key>
+	<data>b3JnLXJvYW0=</data>
+	<key>Empty</key>
+	<dict/>
+	<key>True</key>
+	<true/>
+	<key>False</key>
+	<false/>
+</dict>
+</plist>
+"#;
+
+        // do
+        let value = from_xml_str(content).unwrap();
+
+        // verify
+        let dict = value.as_dict().unwrap();
+        assert_eq!(
+            dict.get("Array"),
+            Some(&Value::Array(vec![
+                Value::from(" a & b "),
+                Value::Integer(-42),
+                Value::Real(1.5)
+            ]))
+        );
+        assert_eq!(dict.get("Data"), Some(&Value::Data(b"org-roam".to_vec())));
+        assert_eq!(dict.get("True"), Some(&Value::Boolean(true)));
+        assert_eq!(to_xml(&value), content);
+    }
+
+    #[test]
+    fn keep_order_of_keys_on_insert() {
+        // arrange
+        let mut dict = Dict::new();
+        dict.insert("b", Value::from("1"));
+        dict.insert("a", Value::from("2"));
+
+        // do
+        let old = dict.insert("b", Value::from("3"));
+
+        // verify
+        assert_eq!(old, Some(Value::from("1")));
+        assert_eq!(
+            dict.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+    }
+
+    #[test]
+    fn get_error_for_broken_plist() {
+        // arrange
+        let content = "<plist><dict><string>no key</string></dict></plist>";
+
+        // do
+        let actual = from_xml_str(content);
+
+        // verify
+        assert_eq!(actual, Err(PlistError::new("<key> is expected in <dict>")));
+    }
+
+    #[test]
+    fn detect_format_of_plist() {
+        // arrange
+        let mut dict = Dict::new();
+        dict.insert("CFBundleName", Value::from("Client"));
+        let value = Value::Dict(dict);
+
+        // do
+        let xml = from_bytes(&to_bytes(&value, Format::Xml).unwrap());
+        let binary = from_bytes(&to_bytes(&value, Format::Binary).unwrap());
+
+        // verify
+        assert_eq!(xml, Ok((value.clone(), Format::Xml)));
+        assert_eq!(binary, Ok((value, Format::Binary)));
+    }
+
+    #[test]
+    fn encode_and_decode_base64() {
+        // arrange
+        // do
+        // verify
+        assert_eq!(encode_base64(b"a"), "YQ==");
+        assert_eq!(encode_base64(b"ab"), "YWI=");
+        assert_eq!(encode_base64(b"abc"), "YWJj");
+        assert_eq!(decode_base64("YW\nJj YQ=="), Some(b"abca".to_vec()));
+    }
+}
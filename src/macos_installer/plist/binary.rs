@@ -0,0 +1,594 @@
+use super::{Dict, PlistError, Value};
+
+pub const BINARY_MAGIC: &[u8] = b"bplist00";
+
+const TRAILER_SIZE: usize = 32;
+
+// seconds from 1970-01-01 to 2001-01-01, the epoch of date in binary plist
+const APPLE_EPOCH: i64 = 978_307_200;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, b| (n << 8) | u64::from(*b))
+}
+
+// bytes needed to write the value as unsigned integer in 1, 2, 4 or 8 bytes
+fn uint_size(value: u64) -> usize {
+    if value <= 0xff {
+        1
+    } else if value <= 0xffff {
+        2
+    } else if value <= 0xffff_ffff {
+        4
+    } else {
+        8
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, value: u64, size: usize) {
+    out.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+}
+
+struct BinaryReader<'a> {
+    content: &'a [u8],
+    offsets: Vec<u64>,
+    ref_size: u64,
+    // objects being parsed, to detect cyclic references
+    stack: Vec<u64>,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn bytes(&self, start: u64, len: u64) -> Result<&'a [u8], PlistError> {
+        let content = self.content;
+
+        match start.checked_add(len) {
+            Some(end) if end <= content.len() as u64 => Ok(&content[start as usize..end as usize]),
+            _ => Err(PlistError::new("object exceeds end of file")),
+        }
+    }
+
+    // length of the object and where its content starts
+    fn length(&self, offset: u64, info: u8) -> Result<(u64, u64), PlistError> {
+        if info != 0x0f {
+            return Ok((u64::from(info), offset + 1));
+        }
+
+        let marker = self.bytes(offset + 1, 1)?[0];
+        if marker >> 4 != 0x1 || marker & 0x0f > 3 {
+            return Err(PlistError::new("invalid length of object"));
+        }
+        let size = 1 << (marker & 0x0f);
+        let len = read_uint(self.bytes(offset + 2, size)?);
+
+        Ok((len, offset + 2 + size))
+    }
+
+    fn refs(&self, start: u64, count: u64) -> Result<Vec<u64>, PlistError> {
+        let len = count
+            .checked_mul(self.ref_size)
+            .ok_or_else(|| PlistError::new("too many references"))?;
+        let bytes = self.bytes(start, len)?;
+
+        Ok(bytes
+            .chunks(self.ref_size as usize)
+            .map(read_uint)
+            .collect())
+    }
+
+    fn read_object(&mut self, index: u64) -> Result<Value, PlistError> {
+        let offset = *self
+            .offsets
+            .get(index as usize)
+            .ok_or_else(|| PlistError::new("object reference out of range"))?;
+        if self.stack.contains(&index) {
+            return Err(PlistError::new("cyclic object reference"));
+        }
+
+        self.stack.push(index);
+        let value = self.parse_object(offset);
+        self.stack.pop();
+
+        value
+    }
+
+    fn parse_object(&mut self, offset: u64) -> Result<Value, PlistError> {
+        let marker = self.bytes(offset, 1)?[0];
+        let info = marker & 0x0f;
+
+        match marker >> 4 {
+            0x0 if info == 0x8 => Ok(Value::Boolean(false)),
+            0x0 if info == 0x9 => Ok(Value::Boolean(true)),
+            0x1 if info <= 4 => {
+                let bytes = self.bytes(offset + 1, 1 << info)?;
+                // 1, 2 and 4 bytes integers are unsigned, and 8 bytes one is signed. 16 bytes
+                // integer has the value in lower 8 bytes.
+                let low = &bytes[bytes.len().saturating_sub(8)..];
+                Ok(Value::Integer(read_uint(low) as i64))
+            }
+            0x2 if info == 2 => {
+                let bytes = self.bytes(offset + 1, 4)?;
+                Ok(Value::Real(f64::from(f32::from_bits(
+                    read_uint(bytes) as u32
+                ))))
+            }
+            0x2 if info == 3 => {
+                let bytes = self.bytes(offset + 1, 8)?;
+                Ok(Value::Real(f64::from_bits(read_uint(bytes))))
+            }
+            0x3 if info == 3 => {
+                let bytes = self.bytes(offset + 1, 8)?;
+                format_date(f64::from_bits(read_uint(bytes))).map(Value::Date)
+            }
+            0x4 => {
+                let (len, start) = self.length(offset, info)?;
+                Ok(Value::Data(self.bytes(start, len)?.to_vec()))
+            }
+            0x8 => {
+                let bytes = self.bytes(offset + 1, u64::from(info) + 1)?;
+                if bytes.len() > 8 {
+                    return Err(PlistError::new("too long UID"));
+                }
+                Ok(Value::Uid(read_uint(bytes)))
+            }
+            0x5 => {
+                let (len, start) = self.length(offset, info)?;
+                String::from_utf8(self.bytes(start, len)?.to_vec())
+                    .map(Value::String)
+                    .map_err(|_| PlistError::new("invalid ASCII string"))
+            }
+            0x6 => {
+                let (len, start) = self.length(offset, info)?;
+                let len = len
+                    .checked_mul(2)
+                    .ok_or_else(|| PlistError::new("too long string"))?;
+                let units = self
+                    .bytes(start, len)?
+                    .chunks(2)
+                    .map(|v| read_uint(v) as u16)
+                    .collect::<Vec<_>>();
+                String::from_utf16(&units)
+                    .map(Value::String)
+                    .map_err(|_| PlistError::new("invalid UTF-16 string"))
+            }
+            0xa => {
+                let (len, start) = self.length(offset, info)?;
+                self.refs(start, len)?
+                    .into_iter()
+                    .map(|v| self.read_object(v))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Array)
+            }
+            0xd => {
+                let (len, start) = self.length(offset, info)?;
+                let keys = self.refs(start, len)?;
+                let values = self.refs(start + len * self.ref_size, len)?;
+                let mut dict = Dict::new();
+
+                for (key, value) in keys.into_iter().zip(values) {
+                    let key = match self.read_object(key)? {
+                        Value::String(key) => key,
+                        _ => return Err(PlistError::new("key of dict must be string")),
+                    };
+                    let value = self.read_object(value)?;
+                    dict.insert(&key, value);
+                }
+
+                Ok(Value::Dict(dict))
+            }
+            _ => Err(PlistError::new(&format!(
+                "unsupported object type 0x{:02x}",
+                marker
+            ))),
+        }
+    }
+}
+
+// parse binary property list, that starts with `bplist00`
+pub fn from_binary(content: &[u8]) -> Result<Value, PlistError> {
+    if !content.starts_with(BINARY_MAGIC) || content.len() < BINARY_MAGIC.len() + TRAILER_SIZE {
+        return Err(PlistError::new("binary plist is too short"));
+    }
+
+    let trailer = &content[content.len() - TRAILER_SIZE..];
+    let offset_size = u64::from(trailer[6]);
+    let ref_size = u64::from(trailer[7]);
+    let count = read_uint(&trailer[8..16]);
+    let top = read_uint(&trailer[16..24]);
+    let table_offset = read_uint(&trailer[24..32]);
+
+    if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+        return Err(PlistError::new("invalid size in trailer"));
+    }
+
+    let mut reader = BinaryReader {
+        content,
+        offsets: Vec::new(),
+        ref_size,
+        stack: Vec::new(),
+    };
+    let table_len = count
+        .checked_mul(offset_size)
+        .ok_or_else(|| PlistError::new("too many objects"))?;
+    reader.offsets = reader
+        .bytes(table_offset, table_len)?
+        .chunks(offset_size as usize)
+        .map(read_uint)
+        .collect();
+
+    reader.read_object(top)
+}
+
+struct BinaryWriter {
+    objects: Vec<Vec<u8>>,
+    ref_size: usize,
+}
+
+// number of objects to be written for the value
+fn count_objects(value: &Value) -> usize {
+    match value {
+        Value::Dict(dict) => {
+            1 + dict
+                .iter()
+                .map(|(_, v)| 1 + count_objects(v))
+                .sum::<usize>()
+        }
+        Value::Array(array) => 1 + array.iter().map(count_objects).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+// marker of the object with its length
+fn header(kind: u8, len: usize) -> Vec<u8> {
+    if len < 0x0f {
+        return vec![(kind << 4) | len as u8];
+    }
+
+    let mut out = vec![(kind << 4) | 0x0f];
+    write_integer(&mut out, len as i64);
+    out
+}
+
+fn write_integer(out: &mut Vec<u8>, value: i64) {
+    if value < 0 {
+        out.push(0x13);
+        write_uint(out, value as u64, 8);
+        return;
+    }
+
+    let size = uint_size(value as u64);
+    out.push(0x10 | size.trailing_zeros() as u8);
+    write_uint(out, value as u64, size);
+}
+
+impl BinaryWriter {
+    fn push_refs(&self, out: &mut Vec<u8>, refs: &[usize]) {
+        for index in refs {
+            write_uint(out, *index as u64, self.ref_size);
+        }
+    }
+
+    fn add(&mut self, value: &Value) -> Result<usize, PlistError> {
+        let index = self.objects.len();
+        self.objects.push(Vec::new());
+
+        let object = match value {
+            Value::Dict(dict) => {
+                let keys = dict
+                    .iter()
+                    .map(|(k, _)| self.add(&Value::from(k)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let values = dict
+                    .iter()
+                    .map(|(_, v)| self.add(v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut out = header(0xd, dict.len());
+                self.push_refs(&mut out, &keys);
+                self.push_refs(&mut out, &values);
+                out
+            }
+            Value::Array(array) => {
+                let values = array
+                    .iter()
+                    .map(|v| self.add(v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut out = header(0xa, array.len());
+                self.push_refs(&mut out, &values);
+                out
+            }
+            Value::String(value) if value.is_ascii() => {
+                let mut out = header(0x5, value.len());
+                out.extend_from_slice(value.as_bytes());
+                out
+            }
+            Value::String(value) => {
+                let units = value.encode_utf16().collect::<Vec<_>>();
+                let mut out = header(0x6, units.len());
+                for unit in units {
+                    out.extend_from_slice(&unit.to_be_bytes());
+                }
+                out
+            }
+            Value::Integer(value) => {
+                let mut out = Vec::new();
+                write_integer(&mut out, *value);
+                out
+            }
+            Value::Real(value) => {
+                let mut out = vec![0x23];
+                out.extend_from_slice(&value.to_be_bytes());
+                out
+            }
+            Value::Boolean(false) => vec![0x08],
+            Value::Boolean(true) => vec![0x09],
+            Value::Date(value) => {
+                let mut out = vec![0x33];
+                out.extend_from_slice(&parse_date(value)?.to_be_bytes());
+                out
+            }
+            Value::Data(value) => {
+                let mut out = header(0x4, value.len());
+                out.extend_from_slice(value);
+                out
+            }
+            Value::Uid(value) => {
+                let size = uint_size(*value);
+                let mut out = vec![0x80 | (size - 1) as u8];
+                write_uint(&mut out, *value, size);
+                out
+            }
+        };
+
+        self.objects[index] = object;
+        Ok(index)
+    }
+}
+
+// serialize property list in binary format
+pub fn to_binary(value: &Value) -> Result<Vec<u8>, PlistError> {
+    let count = count_objects(value);
+    let mut writer = BinaryWriter {
+        objects: Vec::with_capacity(count),
+        ref_size: uint_size(count as u64 - 1),
+    };
+    writer.add(value)?;
+
+    let mut out = Vec::from(BINARY_MAGIC);
+    let mut offsets = Vec::with_capacity(count);
+    for object in &writer.objects {
+        offsets.push(out.len() as u64);
+        out.extend_from_slice(object);
+    }
+
+    let table_offset = out.len() as u64;
+    let offset_size = uint_size(offsets.last().copied().unwrap_or(0));
+    for offset in offsets {
+        write_uint(&mut out, offset, offset_size);
+    }
+
+    out.extend_from_slice(&[0; 6]);
+    out.push(offset_size as u8);
+    out.push(writer.ref_size as u8);
+    write_uint(&mut out, count as u64, 8);
+    write_uint(&mut out, 0, 8);
+    write_uint(&mut out, table_offset, 8);
+
+    Ok(out)
+}
+
+// days from 1970-01-01 to the date in proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+// convert ISO 8601 date, such as `2001-01-01T00:00:00Z` or `2001-01-01T00:00:00.5Z`, to
+// seconds since 2001-01-01
+fn parse_date(value: &str) -> Result<f64, PlistError> {
+    let error = || PlistError::new(&format!("invalid date: {}", value));
+    let bytes = value.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || bytes[10] != b'T'
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || bytes[bytes.len() - 1] != b'Z'
+    {
+        return Err(error());
+    }
+    let field = |start: usize, end: usize| -> Result<i64, PlistError> {
+        value[start..end].parse().map_err(|_| error())
+    };
+    let fraction = match &value[19..value.len() - 1] {
+        "" => 0.0,
+        digits
+            if digits.len() > 1
+                && digits.starts_with('.')
+                && digits[1..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            format!("0{}", digits).parse().map_err(|_| error())?
+        }
+        _ => return Err(error()),
+    };
+
+    let days = days_from_civil(field(0, 4)?, field(5, 7)?, field(8, 10)?);
+    let seconds = field(11, 13)? * 3600 + field(14, 16)? * 60 + field(17, 19)?;
+
+    Ok((days * SECONDS_PER_DAY + seconds - APPLE_EPOCH) as f64 + fraction)
+}
+
+// Convert seconds since 2001-01-01 to ISO 8601 date. Fraction of second is written in
+// shortest digits to read back the same value, because date of binary plist may have it.
+fn format_date(value: f64) -> Result<String, PlistError> {
+    // about 30 million years, to keep the calculation in i64
+    if !value.is_finite() || value.abs() > 1e15 {
+        return Err(PlistError::new(&format!("invalid date: {}", value)));
+    }
+
+    let floor = value.floor();
+    let seconds = floor as i64 + APPLE_EPOCH;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    // Subtraction is exact except in the last second before 2001, and Display of f64 does not
+    // use exponent
+    let fraction = value - floor;
+    let fraction = if fraction == 0.0 {
+        String::new()
+    } else {
+        fraction.to_string().trim_start_matches('0').to_string()
+    };
+
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        fraction
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_and_read_binary_plist() {
+        // arrange
+        let mut dict = Dict::new();
+        dict.insert("a", Value::Boolean(true));
+        let value = Value::Dict(dict);
+
+        // do
+        let content = to_binary(&value).unwrap();
+
+        // verify
+        let mut expected = Vec::from(&b"bplist00"[..]);
+        expected.extend_from_slice(&[0xd1, 0x01, 0x02, 0x51, b'a', 0x09]);
+        expected.extend_from_slice(&[0x08, 0x0b, 0x0d]);
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        expected.extend_from_slice(&3u64.to_be_bytes());
+        expected.extend_from_slice(&0u64.to_be_bytes());
+        expected.extend_from_slice(&14u64.to_be_bytes());
+        assert_eq!(content, expected);
+        assert_eq!(from_binary(&content), Ok(value));
+    }
+
+    #[test]
+    fn read_back_all_types() {
+        // arrange
+        let mut nested = Dict::new();
+        nested.insert("CFBundleURLName", Value::from("org-protocol handler"));
+        nested.insert("Name", Value::from("オルグ"));
+        let mut dict = Dict::new();
+        dict.insert(
+            "Array",
+            Value::Array(vec![
+                Value::Integer(-42),
+                Value::Integer(300),
+                Value::Integer(i64::MAX),
+                Value::Real(1.5),
+                Value::Boolean(false),
+            ]),
+        );
+        dict.insert("Date", Value::Date(String::from("2024-02-29T12:34:56Z")));
+        dict.insert("Data", Value::Data((0..=255).collect()));
+        dict.insert("Nested", Value::Dict(nested));
+        dict.insert("Empty", Value::Array(Vec::new()));
+        let value = Value::Dict(dict);
+
+        // do
+        let actual = from_binary(&to_binary(&value).unwrap());
+
+        // verify
+        assert_eq!(actual, Ok(value));
+    }
+
+    #[test]
+    fn convert_date_from_apple_epoch() {
+        // arrange
+        // do
+        // verify
+        assert_eq!(parse_date("2001-01-01T00:00:00Z"), Ok(0.0));
+        assert_eq!(parse_date("2000-12-31T23:59:59Z"), Ok(-1.0));
+        assert_eq!(
+            format_date(730_000_000.0),
+            Ok(String::from("2024-02-19T01:46:40Z"))
+        );
+        assert_eq!(
+            format_date(730_000_000.5),
+            Ok(String::from("2024-02-19T01:46:40.5Z"))
+        );
+        assert!(parse_date("2001-01-01").is_err());
+        assert!(parse_date("2001-01-01T00:00:00.Z").is_err());
+        assert!(parse_date("2001-01-01T00:00:00.1e3Z").is_err());
+    }
+
+    #[test]
+    fn keep_fraction_of_date_and_uid_on_round_trip() {
+        // arrange
+        let seconds = [-1.25, 0.1, 730_000_000.123_456_7, 1e-7];
+        let mut dates = Vec::new();
+        for value in seconds {
+            let mut out = vec![0x33];
+            out.extend_from_slice(&f64::to_be_bytes(value));
+            dates.push(out);
+        }
+
+        // do
+        let actual = seconds
+            .iter()
+            .map(|v| parse_date(&format_date(*v).unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let value = Value::Array(vec![
+            Value::Uid(0),
+            Value::Uid(0x1234),
+            Value::Date(format_date(seconds[2]).unwrap()),
+        ]);
+        let content = to_binary(&value).unwrap();
+
+        // verify
+        assert_eq!(actual, seconds);
+        assert!(content.windows(9).any(|v| v == dates[2].as_slice()));
+        assert!(content.windows(3).any(|v| v == [0x81, 0x12, 0x34]));
+        assert_eq!(from_binary(&content), Ok(value));
+    }
+
+    #[test]
+    fn get_error_for_broken_binary_plist() {
+        // arrange
+        let mut content = to_binary(&Value::Array(vec![Value::from("a")])).unwrap();
+        let len = content.len();
+        // point the offset table outside of the file
+        content[len - 1] = 0xff;
+
+        // do
+        let actual = from_binary(&content);
+
+        // verify
+        assert_eq!(actual, Err(PlistError::new("object exceeds end of file")));
+    }
+}
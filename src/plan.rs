@@ -0,0 +1,893 @@
+use std::collections::HashSet;
+use std::env;
+use std::ffi::CString;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::diff::unified_diff;
+use crate::linux_installer::mimeapps::{
+    MimeApps, ADDED_ASSOCIATIONS, DEFAULT_APPLICATIONS, REMOVED_ASSOCIATIONS,
+};
+use crate::macos_installer::add_url_type;
+use crate::macos_installer::plist::{self, Dict, Format, Value};
+
+use super::InstallerResult;
+
+// content of file, or None if the file does not exist
+pub type FileContent = Option<Vec<u8>>;
+
+// Side effect on the system made by installers. Installers only make actions, and the
+// executor applies them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    CreateDir(PathBuf),
+    WriteFile {
+        path: PathBuf,
+        content: Vec<u8>,
+        executable: bool,
+    },
+    // file that does not exist is ignored
+    RemoveFile(PathBuf),
+    // remove the directory recursively, such as application bundle
+    RemoveDir(PathBuf),
+    // set desktop file IDs for the MIME type in the group of mimeapps.list, or remove the
+    // MIME type from the group if None
+    EditMimeApps {
+        path: PathBuf,
+        group: String,
+        mime_type: String,
+        desktop_ids: Option<Vec<String>>,
+    },
+    // set the key in top level dict of property list, or remove the key if None
+    EditPlistKey {
+        path: PathBuf,
+        key: String,
+        value: Option<Value>,
+    },
+    // add URL type for org-protocol into CFBundleURLTypes of Info.plist, keeping other URL
+    // types in it
+    AddUrlType(PathBuf),
+    RunCommand {
+        program: PathBuf,
+        args: Vec<String>,
+        // files or directories the command writes, to restore them on failure
+        outputs: Vec<PathBuf>,
+        // arguments to run the program with on failure, to undo other effects of the command
+        undo_args: Option<Vec<String>>,
+    },
+}
+
+impl Action {
+    pub fn write_file(path: &Path, content: &[u8]) -> Action {
+        Action::WriteFile {
+            path: path.to_path_buf(),
+            content: content.to_vec(),
+            executable: false,
+        }
+    }
+
+    pub fn run_command(program: &Path, args: &[String]) -> Action {
+        Action::RunCommand {
+            program: program.to_path_buf(),
+            args: args.to_vec(),
+            outputs: Vec::new(),
+            undo_args: None,
+        }
+    }
+
+    // path of file or directory the action touches, or None for command
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Action::CreateDir(path)
+            | Action::WriteFile { path, .. }
+            | Action::RemoveFile(path)
+            | Action::RemoveDir(path)
+            | Action::EditMimeApps { path, .. }
+            | Action::EditPlistKey { path, .. }
+            | Action::AddUrlType(path) => Some(path),
+            Action::RunCommand { .. } => None,
+        }
+    }
+
+    // Content of the file after the action, from content before it. Actions not editing file
+    // return the content as is.
+    pub fn apply_to(&self, content: FileContent) -> InstallerResult<FileContent> {
+        match self {
+            Action::WriteFile { content, .. } => Ok(Some(content.clone())),
+            Action::RemoveFile(_) => Ok(None),
+            Action::EditMimeApps {
+                group,
+                mime_type,
+                desktop_ids,
+                ..
+            } => {
+                let content = content.unwrap_or_default();
+                let mut list = MimeApps::parse(&String::from_utf8_lossy(&content));
+                match desktop_ids {
+                    Some(ids) => {
+                        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+                        list.set(group, mime_type, &ids);
+                    }
+                    None => {
+                        list.remove(group, mime_type);
+                    }
+                }
+
+                Ok(Some(list.to_string().into_bytes()))
+            }
+            Action::EditPlistKey { path, key, value } => {
+                edit_plist(path, content, |dict| match value {
+                    Some(value) => dict.insert(key, value.clone()).as_ref() != Some(value),
+                    None => dict.remove(key).is_some(),
+                })
+            }
+            Action::AddUrlType(path) => edit_plist(path, content, add_url_type),
+            Action::CreateDir(_) | Action::RemoveDir(_) | Action::RunCommand { .. } => Ok(content),
+        }
+    }
+
+    // Actions to make entries of the MIME type in mimeapps.list the same as edited
+    pub fn mimeapps_edits(
+        path: &Path,
+        mime_type: &str,
+        original: &MimeApps,
+        edited: &MimeApps,
+    ) -> Vec<Action> {
+        [
+            DEFAULT_APPLICATIONS,
+            ADDED_ASSOCIATIONS,
+            REMOVED_ASSOCIATIONS,
+        ]
+        .iter()
+        .filter(|group| original.get(group, mime_type) != edited.get(group, mime_type))
+        .map(|group| Action::EditMimeApps {
+            path: path.to_path_buf(),
+            group: String::from(*group),
+            mime_type: String::from(mime_type),
+            desktop_ids: edited.get(group, mime_type),
+        })
+        .collect()
+    }
+
+    // Actions to make keys of top level dict in property list the same as edited
+    pub fn plist_edits(path: &Path, original: &Dict, edited: &Dict) -> Vec<Action> {
+        let mut actions = edited
+            .iter()
+            .filter(|(key, value)| original.get(key) != Some(*value))
+            .map(|(key, value)| Action::EditPlistKey {
+                path: path.to_path_buf(),
+                key: String::from(key),
+                value: Some(value.clone()),
+            })
+            .collect::<Vec<_>>();
+
+        actions.extend(
+            original
+                .iter()
+                .filter(|(key, _)| edited.get(key).is_none())
+                .map(|(key, _)| Action::EditPlistKey {
+                    path: path.to_path_buf(),
+                    key: String::from(key),
+                    value: None,
+                }),
+        );
+        actions
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CreateDir(path) => write!(f, "Create directory {}", path.display()),
+            Action::WriteFile { path, .. } => write!(f, "Write {}", path.display()),
+            Action::RemoveFile(path) => write!(f, "Remove {}", path.display()),
+            Action::RemoveDir(path) => write!(f, "Remove directory {}", path.display()),
+            Action::EditMimeApps {
+                path,
+                group,
+                mime_type,
+                desktop_ids: Some(ids),
+            } => write!(
+                f,
+                "Set {}={}; in [{}] of {}",
+                mime_type,
+                ids.join(";"),
+                group,
+                path.display()
+            ),
+            Action::EditMimeApps {
+                path,
+                group,
+                mime_type,
+                desktop_ids: None,
+            } => write!(
+                f,
+                "Remove {} from [{}] of {}",
+                mime_type,
+                group,
+                path.display()
+            ),
+            Action::EditPlistKey {
+                path,
+                key,
+                value: Some(_),
+            } => write!(f, "Set {} in {}", key, path.display()),
+            Action::EditPlistKey {
+                path,
+                key,
+                value: None,
+            } => write!(f, "Remove {} from {}", key, path.display()),
+            Action::AddUrlType(path) => {
+                write!(
+                    f,
+                    "Add org-protocol to CFBundleURLTypes in {}",
+                    path.display()
+                )
+            }
+            Action::RunCommand { program, args, .. } => {
+                write!(f, "Run {}", program.display())?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Actions to apply in order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Plan {
+    actions: Vec<Action>,
+    // commands already run while planning, that do not change the system
+    queries: Vec<Action>,
+}
+
+impl Plan {
+    pub fn new() -> Self {
+        Plan::default()
+    }
+
+    pub fn push(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    // record command run while planning, to show it in dry run
+    pub fn push_query(&mut self, program: &Path, args: &[String]) {
+        self.queries.push(Action::run_command(program, args));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    // Check the plan can be applied without touching the system: directories and files are
+    // writable, and commands exist. All problems found are reported at once.
+    pub fn preflight(&self) -> InstallerResult<()> {
+        let mut problems = Vec::new();
+        let mut checked = HashSet::new();
+
+        for action in &self.actions {
+            let path = match (action, action.path()) {
+                (Action::RunCommand { program, .. }, _) => {
+                    if !command_exists(program) {
+                        problems.push(format!("Can not find command {}", program.display()));
+                    }
+                    continue;
+                }
+                (_, Some(path)) => path,
+                (_, None) => continue,
+            };
+
+            let dir = match action {
+                Action::CreateDir(_) => path,
+                _ => path.parent().unwrap_or_else(|| Path::new("")),
+            };
+            if checked.insert(dir.to_path_buf()) && !is_writable_dir(dir)? {
+                problems.push(format!("Can not write into {}", dir.display()));
+            }
+            if path.is_file() && !is_writable_file(path)? {
+                problems.push(format!("Can not write {}", path.display()));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n").into())
+        }
+    }
+
+    // Existing files changed or deleted by the plan, with content before and after. Actions
+    // are applied in memory, so several edits to the same file are shown as one change.
+    // Content written by commands is not known, so existing outputs of commands are shown
+    // as recreated, and edits to files in them are not shown.
+    pub fn file_changes(&self) -> InstallerResult<Vec<FileChange>> {
+        // path, content before the plan, and content after actions applied so far
+        let mut files: Vec<(&Path, FileContent, FileContent)> = Vec::new();
+        let mut recreated = Vec::new();
+        let mut outputs: Vec<&Path> = Vec::new();
+
+        for action in &self.actions {
+            let path = match action {
+                Action::RunCommand {
+                    program,
+                    outputs: paths,
+                    ..
+                } => {
+                    for output in paths {
+                        files.retain(|(path, ..)| !path.starts_with(output));
+                        if fs::symlink_metadata(output).is_ok()
+                            && !outputs.iter().any(|v| output.starts_with(v))
+                        {
+                            recreated.push(FileChange::Recreate {
+                                path: output.clone(),
+                                program: program.clone(),
+                            });
+                        }
+                        outputs.push(output);
+                    }
+                    continue;
+                }
+                Action::WriteFile { path, .. }
+                | Action::RemoveFile(path)
+                | Action::EditMimeApps { path, .. }
+                | Action::EditPlistKey { path, .. }
+                | Action::AddUrlType(path) => path.as_path(),
+                _ => continue,
+            };
+            if outputs.iter().any(|v| path.starts_with(v)) {
+                continue;
+            }
+            let index = match files.iter().position(|(p, ..)| *p == path) {
+                Some(index) => index,
+                None => {
+                    let original = read_file(path)?;
+                    files.push((path, original.clone(), original));
+                    files.len() - 1
+                }
+            };
+
+            let current = files[index].2.take();
+            files[index].2 = action.apply_to(current)?;
+        }
+
+        recreated.extend(files.into_iter().filter_map(|(path, original, current)| {
+            let original = original?;
+            if Some(&original) == current.as_ref() {
+                return None;
+            }
+
+            Some(FileChange::Edit {
+                path: path.to_path_buf(),
+                old: text_of(&original),
+                new: current.map(|v| text_of(&v)),
+            })
+        }));
+        Ok(recreated)
+    }
+
+    // Files and directories that would be created, changed or deleted, and commands that
+    // would run, for dry run. Paths touched by several actions are listed once.
+    pub fn dry_run_report(&self) -> String {
+        let mut created = Vec::new();
+        let mut changed = Vec::new();
+        let mut deleted = Vec::new();
+        let mut commands = Vec::new();
+        let mut seen = HashSet::new();
+
+        for action in &self.actions {
+            let path = match action.path() {
+                Some(path) => path,
+                None => {
+                    commands.push(action.to_string());
+                    continue;
+                }
+            };
+            if !seen.insert(path) {
+                continue;
+            }
+
+            let exists = fs::symlink_metadata(path).is_ok();
+            match action {
+                Action::CreateDir(_) if exists => (),
+                Action::RemoveFile(_) | Action::RemoveDir(_) if !exists => (),
+                Action::RemoveFile(_) | Action::RemoveDir(_) => deleted.push(path),
+                _ if exists => changed.push(path),
+                _ => created.push(path),
+            }
+        }
+
+        let mut report = String::new();
+        for (title, paths) in [
+            ("create", created),
+            ("change", changed),
+            ("delete", deleted),
+        ] {
+            if !paths.is_empty() {
+                report.push_str(&format!("Would {}:\n", title));
+                for path in paths {
+                    report.push_str(&format!("  {}\n", path.display()));
+                }
+            }
+        }
+        if !commands.is_empty() {
+            report.push_str("Would run:\n");
+            for command in commands {
+                report.push_str(&format!("  {}\n", command.trim_start_matches("Run ")));
+            }
+        }
+        if report.is_empty() {
+            report.push_str("Nothing to do\n");
+        }
+        if !self.queries.is_empty() {
+            report.push_str("Ran to make the plan:\n");
+            for query in &self.queries {
+                report.push_str(&format!(
+                    "  {}\n",
+                    query.to_string().trim_start_matches("Run ")
+                ));
+            }
+        }
+
+        report
+    }
+}
+
+// Existing file changed or deleted by the plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    Edit {
+        path: PathBuf,
+        old: String,
+        // None if deleted
+        new: Option<String>,
+    },
+    // file or directory replaced by output of the command
+    Recreate {
+        path: PathBuf,
+        program: PathBuf,
+    },
+}
+
+impl FileChange {
+    pub fn diff(&self) -> String {
+        match self {
+            FileChange::Edit { path, old, new } => {
+                let path = path.to_string_lossy();
+                match new {
+                    Some(new) => unified_diff(old, new, &path, &path),
+                    None => unified_diff(old, "", &path, "/dev/null"),
+                }
+            }
+            FileChange::Recreate { path, program } => {
+                format!("{} is recreated by {}\n", path.display(), program.display())
+            }
+        }
+    }
+}
+
+// Edit top level dict of property list, with function returning true if changed. The content
+// is returned as is if not changed. Otherwise property list is written back in the format it
+// was read in. LaunchServices writes its preferences in binary, so new file is also written
+// in binary.
+fn edit_plist<F>(path: &Path, content: FileContent, edit: F) -> InstallerResult<FileContent>
+where
+    F: FnOnce(&mut Dict) -> bool,
+{
+    let (mut plist, format) = match &content {
+        Some(content) => plist::from_bytes(content)?,
+        None => (Value::Dict(Dict::new()), Format::Binary),
+    };
+    let dict = plist
+        .as_dict_mut()
+        .ok_or_else(|| format!("Top level of {} is not dict", path.display()))?;
+    if !edit(dict) {
+        return Ok(content);
+    }
+
+    Ok(Some(plist::to_bytes(&plist, format)?))
+}
+
+// text to show in diff. Binary property list is shown in XML.
+fn text_of(content: &[u8]) -> String {
+    if Format::detect(content) == Format::Binary {
+        if let Ok(value) = plist::from_binary(content) {
+            return plist::to_xml(&value);
+        }
+    }
+
+    String::from_utf8_lossy(content).to_string()
+}
+
+pub fn read_file(path: &Path) -> io::Result<FileContent> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Return true if the process can write into the directory. The nearest existing ancestor is
+// checked instead when the directory does not exist yet.
+pub fn is_writable_dir(dir: &Path) -> io::Result<bool> {
+    let mut dir = dir;
+    while !dir.exists() {
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => return Ok(true),
+        }
+    }
+
+    is_writable_file(dir)
+}
+
+// Check permission with access(2), not to touch anything even in dry run
+fn is_writable_file(path: &Path) -> io::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // access only reads the path given as NUL terminated string
+    if unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EPERM) | Some(libc::EROFS) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+// Command given by name is searched in PATH, as Command does
+fn command_exists(program: &Path) -> bool {
+    if program.components().count() > 1 {
+        return program.is_file();
+    }
+
+    env::var_os("PATH")
+        .map(|v| env::split_paths(&v).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+impl Extend<Action> for Plan {
+    fn extend<T: IntoIterator<Item = Action>>(&mut self, iter: T) {
+        self.actions.extend(iter);
+    }
+}
+
+impl From<Vec<Action>> for Plan {
+    fn from(actions: Vec<Action>) -> Self {
+        Plan {
+            actions,
+            queries: Vec::new(),
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn make_edits_only_for_changed_groups() {
+        // arrange
+        let original = MimeApps::parse(
+            "[Default Applications]\nx-scheme-handler/org-protocol=emacsclient.desktop;\n",
+        );
+        let mut edited = original.clone();
+        edited.remove(DEFAULT_APPLICATIONS, "x-scheme-handler/org-protocol");
+        edited.add_association("x-scheme-handler/org-protocol", "org-protocol.desktop");
+
+        // do
+        let actual = Action::mimeapps_edits(
+            Path::new("mimeapps.list"),
+            "x-scheme-handler/org-protocol",
+            &original,
+            &edited,
+        );
+
+        // verify
+        assert_eq!(
+            actual
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Remove x-scheme-handler/org-protocol from [Default Applications] of mimeapps.list",
+                "Set x-scheme-handler/org-protocol=org-protocol.desktop; in [Added Associations] of mimeapps.list",
+            ]
+        );
+    }
+
+    #[test]
+    fn make_edits_only_for_changed_keys() {
+        // arrange
+        let mut original = Dict::new();
+        original.insert("CFBundleName", Value::from("Client"));
+        original.insert("LSUIElement", Value::Boolean(true));
+        let mut edited = original.clone();
+        edited.remove("LSUIElement");
+        edited.insert("CFBundleIdentifier", Value::from("org.example.client"));
+
+        // do
+        let actual = Action::plist_edits(Path::new("Info.plist"), &original, &edited);
+
+        // verify
+        assert_eq!(
+            actual,
+            vec![
+                Action::EditPlistKey {
+                    path: PathBuf::from("Info.plist"),
+                    key: String::from("CFBundleIdentifier"),
+                    value: Some(Value::from("org.example.client")),
+                },
+                Action::EditPlistKey {
+                    path: PathBuf::from("Info.plist"),
+                    key: String::from("LSUIElement"),
+                    value: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_plist_as_is_if_url_type_registered() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("Info.plist");
+        let original = r#"<plist version="1.0"><dict><key>CFBundleName</key><string>Client</string></dict></plist>"#;
+        let action = Action::AddUrlType(path.clone());
+
+        // do
+        let added = action.apply_to(Some(original.as_bytes().to_vec())).unwrap();
+        let again = action.apply_to(added.clone()).unwrap();
+
+        // verify
+        assert_ne!(added.as_deref(), Some(original.as_bytes()));
+        assert_eq!(again, added);
+        fs::write(&path, added.unwrap()).unwrap();
+        assert_eq!(
+            Plan::from(vec![action]).file_changes().unwrap(),
+            Vec::<FileChange>::new()
+        );
+    }
+
+    #[test]
+    fn keep_plist_as_is_if_key_not_changed() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secure.plist");
+        let original = r#"<plist version="1.0"><dict><key>LSHandlersVersion</key><integer>1</integer></dict></plist>"#;
+        fs::write(&path, original).unwrap();
+        let remove = Action::EditPlistKey {
+            path: path.clone(),
+            key: String::from("LSHandlers"),
+            value: None,
+        };
+        let insert = Action::EditPlistKey {
+            path: path.clone(),
+            key: String::from("LSHandlersVersion"),
+            value: Some(Value::Integer(1)),
+        };
+
+        // do
+        let removed = remove.apply_to(Some(original.as_bytes().to_vec())).unwrap();
+        let inserted = insert.apply_to(Some(original.as_bytes().to_vec())).unwrap();
+        let removed_from_missing = remove.apply_to(None).unwrap();
+
+        // verify
+        assert_eq!(removed.as_deref(), Some(original.as_bytes()));
+        assert_eq!(inserted.as_deref(), Some(original.as_bytes()));
+        assert_eq!(removed_from_missing, None);
+        assert_eq!(
+            Plan::from(vec![remove, insert]).file_changes().unwrap(),
+            Vec::<FileChange>::new()
+        );
+    }
+
+    #[test]
+    fn report_files_and_commands_for_dry_run() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let existing = dir.path().join("mimeapps.list");
+        fs::write(&existing, "").unwrap();
+        let plan = Plan::from(vec![
+            Action::CreateDir(dir.path().to_path_buf()),
+            Action::CreateDir(dir.path().join("applications")),
+            Action::write_file(&dir.path().join("applications/org-protocol.desktop"), b""),
+            Action::EditMimeApps {
+                path: existing.clone(),
+                group: String::from(DEFAULT_APPLICATIONS),
+                mime_type: String::from("x-scheme-handler/org-protocol"),
+                desktop_ids: None,
+            },
+            Action::EditMimeApps {
+                path: existing.clone(),
+                group: String::from(ADDED_ASSOCIATIONS),
+                mime_type: String::from("x-scheme-handler/org-protocol"),
+                desktop_ids: None,
+            },
+            Action::RemoveFile(dir.path().join("missing.desktop")),
+            Action::run_command(Path::new("update-desktop-database"), &[String::from("dir")]),
+        ]);
+
+        // do
+        let actual = plan.dry_run_report();
+
+        // verify
+        assert_eq!(
+            actual,
+            format!(
+                "Would create:\n  {}\n  {}\nWould change:\n  {}\nWould run:\n  update-desktop-database dir\n",
+                dir.path().join("applications").display(),
+                dir.path().join("applications/org-protocol.desktop").display(),
+                existing.display()
+            )
+        );
+        assert_eq!(Plan::new().dry_run_report(), "Nothing to do\n");
+    }
+
+    #[test]
+    fn report_commands_run_while_planning() {
+        // arrange
+        let mut plan = Plan::new();
+        plan.push_query(
+            Path::new("/usr/local/bin/emacsclient"),
+            &[String::from("--version")],
+        );
+
+        // do
+        let actual = plan.dry_run_report();
+
+        // verify
+        assert_eq!(
+            actual,
+            "Nothing to do\nRan to make the plan:\n  /usr/local/bin/emacsclient --version\n"
+        );
+    }
+
+    #[test]
+    fn check_writable_without_writing() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let plan = Plan::from(vec![
+            Action::CreateDir(dir.path().join("applications")),
+            Action::write_file(&dir.path().join("org-protocol.desktop"), b""),
+        ]);
+
+        // do
+        let actual = plan.preflight();
+
+        // verify
+        assert!(actual.is_ok());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn fail_preflight_if_command_not_found() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let plan = Plan::from(vec![
+            Action::write_file(&dir.path().join("org-protocol.desktop"), b""),
+            Action::run_command(&dir.path().join("missing-command"), &[]),
+        ]);
+
+        // do
+        let actual = plan.preflight();
+
+        // verify
+        assert_eq!(
+            actual.unwrap_err().to_string(),
+            format!(
+                "Can not find command {}",
+                dir.path().join("missing-command").display()
+            )
+        );
+        assert!(Plan::from(vec![Action::run_command(Path::new("sh"), &[])])
+            .preflight()
+            .is_ok());
+    }
+
+    #[test]
+    fn collect_changes_of_existing_files() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let list = dir.path().join("mimeapps.list");
+        fs::write(
+            &list,
+            "[Default Applications]\nx-scheme-handler/org-protocol=emacsclient.desktop;\n",
+        )
+        .unwrap();
+        let same = dir.path().join("same.desktop");
+        fs::write(&same, "[Desktop Entry]\n").unwrap();
+        let plan = Plan::from(vec![
+            Action::write_file(&dir.path().join("new.desktop"), b"[Desktop Entry]\n"),
+            Action::write_file(&same, b"[Desktop Entry]\n"),
+            Action::EditMimeApps {
+                path: list.clone(),
+                group: String::from(DEFAULT_APPLICATIONS),
+                mime_type: String::from("x-scheme-handler/org-protocol"),
+                desktop_ids: Some(vec![String::from("org-protocol.desktop")]),
+            },
+            Action::EditMimeApps {
+                path: list.clone(),
+                group: String::from(ADDED_ASSOCIATIONS),
+                mime_type: String::from("x-scheme-handler/org-protocol"),
+                desktop_ids: Some(vec![String::from("org-protocol.desktop")]),
+            },
+        ]);
+
+        // do
+        let actual = plan.file_changes().unwrap();
+
+        // verify
+        assert_eq!(
+            actual,
+            vec![FileChange::Edit {
+                path: list,
+                old: String::from(
+                    "[Default Applications]\nx-scheme-handler/org-protocol=emacsclient.desktop;\n"
+                ),
+                new: Some(String::from(
+                    "[Default Applications]\nx-scheme-handler/org-protocol=org-protocol.desktop;\n\n[Added Associations]\nx-scheme-handler/org-protocol=org-protocol.desktop;\n"
+                )),
+            }]
+        );
+    }
+
+    #[test]
+    fn show_output_of_command_as_recreated() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("OrgProtocolClient.app");
+        let info = bundle.join("Contents/Info.plist");
+        fs::create_dir_all(info.parent().unwrap()).unwrap();
+        fs::write(&info, plist::to_xml(&Value::Dict(Dict::new()))).unwrap();
+        let plan = Plan::from(vec![
+            Action::RunCommand {
+                program: PathBuf::from("osacompile"),
+                args: vec![],
+                outputs: vec![bundle.clone()],
+                undo_args: None,
+            },
+            Action::AddUrlType(info.clone()),
+        ]);
+
+        // do
+        let actual = plan.file_changes().unwrap();
+        fs::remove_dir_all(&bundle).unwrap();
+        let created = plan.file_changes().unwrap();
+
+        // verify
+        assert_eq!(
+            actual,
+            vec![FileChange::Recreate {
+                path: bundle.clone(),
+                program: PathBuf::from("osacompile"),
+            }]
+        );
+        assert_eq!(
+            actual[0].diff(),
+            format!("{} is recreated by osacompile\n", bundle.display())
+        );
+        assert_eq!(created, vec![]);
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// profiles of Nix that link to binaries in the store, relative to the root
+const NIX_PROFILE_DIRS: &[&str] = &["run/current-system/sw", "nix/var/nix/profiles/default"];
+
+// Nix profile of user, relative to home directory
+const NIX_USER_PROFILE_DIR: &str = ".nix-profile";
+
+fn names(path: &Path) -> Vec<&str> {
+    path.components()
+        .filter_map(|v| match v {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect()
+}
+
+// Position of Cellar or Caskroom of Homebrew in the path
+fn homebrew_position(names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .position(|v| *v == "Cellar" || *v == "Caskroom")
+}
+
+// Position of `nix/store` in the path
+fn nix_store_position(names: &[&str]) -> Option<usize> {
+    names.windows(2).position(|v| v == ["nix", "store"])
+}
+
+// Return true if the path contains version, such as Cellar of Homebrew or the store of Nix.
+// Such path is removed on upgrade.
+pub fn is_version_pinned(path: &Path) -> bool {
+    let names = names(path);
+
+    homebrew_position(&names).is_some() || nix_store_position(&names).is_some()
+}
+
+// join names to the ancestor of the path, that is count levels above
+fn rebase(path: &Path, count: usize, names: &[&str]) -> PathBuf {
+    let mut base = path.to_path_buf();
+    for _ in 0..count {
+        base.pop();
+    }
+    names.iter().fold(base, |path, v| path.join(v))
+}
+
+// Symlinks that do not change on upgrade, and may link to the version pinned path
+fn stable_candidates(path: &Path, home: Option<&Path>) -> Vec<PathBuf> {
+    let names = names(path);
+    let mut candidates = Vec::new();
+
+    if let Some(pos) = homebrew_position(&names) {
+        // <prefix>/Cellar/<formula>/<version>/<rest>
+        if names.len() > pos + 3 {
+            let formula = names[pos + 1];
+            let rest = &names[pos + 3..];
+            let to_prefix = names.len() - pos;
+            let mut opt = vec!["opt", formula];
+            opt.extend(rest);
+            candidates.push(rebase(path, to_prefix, &opt));
+
+            if let Some(file_name) = rest.last() {
+                candidates.push(rebase(path, to_prefix, &["bin", file_name]));
+            }
+        }
+    }
+
+    if let Some(pos) = nix_store_position(&names) {
+        // <root>/nix/store/<hash>-<name>/<rest>
+        if names.len() > pos + 3 {
+            let rest = &names[pos + 3..];
+            let to_root = names.len() - pos;
+
+            if let Some(home) = home {
+                candidates.push(
+                    rest.iter()
+                        .fold(home.join(NIX_USER_PROFILE_DIR), |p, v| p.join(v)),
+                );
+            }
+            for dir in NIX_PROFILE_DIRS {
+                let mut profile = dir.split('/').collect::<Vec<_>>();
+                profile.extend(rest);
+                candidates.push(rebase(path, to_root, &profile));
+            }
+        }
+    }
+
+    candidates
+}
+
+// Find stable symlink that resolves to the same file as the version pinned path
+pub fn stable_alternative(path: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    if !is_version_pinned(path) {
+        return None;
+    }
+    let real = fs::canonicalize(path).ok()?;
+
+    stable_candidates(path, home)
+        .into_iter()
+        .find(|v| fs::canonicalize(v).ok().as_ref() == Some(&real))
+}
+
+// Stable path to rewrite the path to, or None if the path is not version pinned
+pub fn repaired(path: &Path, home: Option<&Path>) -> Result<Option<PathBuf>, String> {
+    if !is_version_pinned(path) {
+        return Ok(None);
+    }
+
+    stable_alternative(path, home).map(Some).ok_or_else(|| {
+        format!(
+            "{} contains version, but no symlink that does not change on upgrade links to it",
+            path.display()
+        )
+    })
+}
+
+// Warning for version pinned path, with stable path to use instead if found
+pub fn warning(path: &Path, home: Option<&Path>) -> Option<String> {
+    if !is_version_pinned(path) {
+        return None;
+    }
+
+    let message = format!(
+        "{} contains version, so the handler will break on upgrade.",
+        path.display()
+    );
+    match stable_alternative(path, home) {
+        Some(stable) => Some(format!(
+            "{} Use {} instead, or run repair mode after installation",
+            message,
+            stable.display()
+        )),
+        None => Some(format!(
+            "{} Use symlink that does not change on upgrade instead",
+            message
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn detect_version_pinned_path() {
+        // arrange
+        // do
+        // verify
+        assert!(is_version_pinned(Path::new(
+            "/opt/homebrew/Cellar/emacs-plus@29/29.1/bin/emacsclient"
+        )));
+        assert!(is_version_pinned(Path::new(
+            "/nix/store/0123abcd-emacs-29.1/bin/emacsclient"
+        )));
+        assert!(!is_version_pinned(Path::new(
+            "/opt/homebrew/bin/emacsclient"
+        )));
+        assert!(!is_version_pinned(Path::new("emacsclient")));
+    }
+
+    #[test]
+    fn find_homebrew_opt_symlink() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let prefix = dir.path().join("opt/homebrew");
+        let pinned = prefix.join("Cellar/emacs-plus@29/29.1/bin/emacsclient");
+        fs::create_dir_all(pinned.parent().unwrap()).unwrap();
+        fs::write(&pinned, "").unwrap();
+        fs::create_dir_all(prefix.join("opt")).unwrap();
+        symlink(
+            "../Cellar/emacs-plus@29/29.1",
+            prefix.join("opt/emacs-plus@29"),
+        )
+        .unwrap();
+
+        // do
+        let actual = stable_alternative(&pinned, None);
+
+        // verify
+        assert_eq!(
+            actual,
+            Some(prefix.join("opt/emacs-plus@29/bin/emacsclient"))
+        );
+    }
+
+    #[test]
+    fn find_nix_profile_symlink() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let home = dir.path().join("home/foo");
+        let pinned = dir
+            .path()
+            .join("nix/store/0123abcd-emacs-29.1/bin/emacsclient");
+        fs::create_dir_all(pinned.parent().unwrap()).unwrap();
+        fs::write(&pinned, "").unwrap();
+        fs::create_dir_all(home.join(".nix-profile/bin")).unwrap();
+        symlink(&pinned, home.join(".nix-profile/bin/emacsclient")).unwrap();
+
+        // do
+        let actual = stable_alternative(&pinned, Some(&home));
+        let message = warning(&pinned, None).unwrap();
+
+        // verify
+        assert_eq!(actual, Some(home.join(".nix-profile/bin/emacsclient")));
+        assert!(message.ends_with("Use symlink that does not change on upgrade instead"));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::command::CommandRunner;
+
+// suffix of file or directory moved aside until the transaction is committed
+const BACKUP_SUFFIX: &str = ".org-roam-protocol-backup";
+
+enum Undo {
+    // write back content and permissions of the file changed or deleted
+    Restore {
+        path: PathBuf,
+        content: Vec<u8>,
+        permissions: fs::Permissions,
+    },
+    // remove file or directory created
+    Remove(PathBuf),
+    // move back file or directory moved aside
+    MoveBack {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    // run command to undo command run
+    Run {
+        program: PathBuf,
+        args: Vec<String>,
+    },
+}
+
+// Record of changes to undo them on failure. Record the original state before each change,
+// then commit or roll back at the end.
+#[derive(Default)]
+pub struct Transaction {
+    undo: Vec<Undo>,
+    // paths already recorded. The first record keeps the original state.
+    recorded: HashSet<PathBuf>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Transaction::default()
+    }
+
+    // Back up the file before changing or deleting it, or record it as created if it does not
+    // exist yet.
+    pub fn backup_file(&mut self, path: &Path) -> io::Result<()> {
+        if !self.recorded.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => self.undo.push(Undo::Restore {
+                path: path.to_path_buf(),
+                content: fs::read(path)?,
+                permissions: metadata.permissions(),
+            }),
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.undo.push(Undo::Remove(path.to_path_buf()))
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    // Record the outermost directory to be created for the path, if any
+    pub fn record_created_dir(&mut self, path: &Path) {
+        let created = path
+            .ancestors()
+            .take_while(|v| !v.as_os_str().is_empty() && fs::symlink_metadata(v).is_err())
+            .last();
+
+        if let Some(created) = created {
+            if self.recorded.insert(created.to_path_buf()) {
+                self.undo.push(Undo::Remove(created.to_path_buf()));
+            }
+        }
+    }
+
+    // Move the file or directory aside, instead of deleting or overwriting it. It is deleted on
+    // commit. The path is recorded as created if it does not exist.
+    pub fn move_aside(&mut self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path).is_err() {
+            if self.recorded.insert(path.to_path_buf()) {
+                self.undo.push(Undo::Remove(path.to_path_buf()));
+            }
+            return Ok(());
+        }
+
+        let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(BACKUP_SUFFIX);
+        let backup = path.with_file_name(name);
+        remove_path(&backup)?;
+        fs::rename(path, &backup)?;
+
+        self.recorded.insert(path.to_path_buf());
+        self.undo.push(Undo::MoveBack {
+            path: path.to_path_buf(),
+            backup,
+        });
+        Ok(())
+    }
+
+    // Record the command to run on rollback, to undo effects of command run that are not files
+    pub fn record_command(&mut self, program: &Path, args: &[String]) {
+        self.undo.push(Undo::Run {
+            program: program.to_path_buf(),
+            args: args.to_vec(),
+        });
+    }
+
+    // Keep the changes, and delete files moved aside
+    pub fn commit(self) -> io::Result<()> {
+        for undo in self.undo {
+            if let Undo::MoveBack { backup, .. } = undo {
+                remove_path(&backup)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Undo the changes in reverse order, running commands with the runner. Return errors while
+    // undoing, that do not stop undoing the rest.
+    pub fn rollback(self, runner: &mut dyn CommandRunner) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        for undo in self.undo.into_iter().rev() {
+            // what is undone, and result of undoing it
+            let (target, result) = match &undo {
+                Undo::Restore {
+                    path,
+                    content,
+                    permissions,
+                } => (
+                    format!("change to {}", path.display()),
+                    fs::write(path, content)
+                        .and_then(|_| fs::set_permissions(path, permissions.clone()))
+                        .map_err(|e| e.into()),
+                ),
+                Undo::Remove(path) => (
+                    format!("change to {}", path.display()),
+                    remove_path(path).map_err(|e| e.into()),
+                ),
+                Undo::MoveBack { path, backup } => (
+                    format!("change to {}", path.display()),
+                    remove_path(path)
+                        .and_then(|_| fs::rename(backup, path))
+                        .map_err(|e| e.into()),
+                ),
+                Undo::Run { program, args } => (
+                    format!("by running {} {}", program.display(), args.join(" ")),
+                    runner.run(program, args).map(|_| ()),
+                ),
+            };
+
+            match result {
+                Ok(_) => println!("Undid {}", target),
+                Err(e) => errors.push(format!("Can not undo {}: {}", target, e)),
+            }
+        }
+
+        errors
+    }
+}
+
+// remove file or directory. Path that does not exist is ignored.
+fn remove_path(path: &Path) -> io::Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use crate::command::RecordingCommandRunner;
+
+    use super::*;
+
+    #[test]
+    fn undo_changes_on_rollback() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let changed = dir.path().join("mimeapps.list");
+        fs::write(&changed, "original").unwrap();
+        let bundle = dir.path().join("Client.app");
+        fs::create_dir_all(bundle.join("Contents")).unwrap();
+        let created_dir = dir.path().join("data/applications");
+        let created = created_dir.join("org-protocol.desktop");
+        let mut transaction = Transaction::new();
+
+        // do
+        transaction.backup_file(&changed).unwrap();
+        fs::write(&changed, "changed").unwrap();
+        transaction.backup_file(&changed).unwrap();
+        fs::write(&changed, "changed again").unwrap();
+        transaction.record_created_dir(&created_dir);
+        fs::create_dir_all(&created_dir).unwrap();
+        transaction.backup_file(&created).unwrap();
+        fs::write(&created, "").unwrap();
+        transaction.move_aside(&bundle).unwrap();
+        transaction.record_command(Path::new("lsregister"), &[String::from("-u")]);
+        let mut runner = RecordingCommandRunner::default();
+        let errors = transaction.rollback(&mut runner);
+
+        // verify
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(runner.commands(), vec![vec!["lsregister", "-u"]]);
+        assert_eq!(fs::read_to_string(&changed).unwrap(), "original");
+        assert!(!dir.path().join("data").exists());
+        assert!(bundle.join("Contents").is_dir());
+    }
+
+    #[test]
+    fn delete_moved_aside_on_commit() {
+        // arrange
+        let dir = TempDir::new().unwrap();
+        let bundle = dir.path().join("Client.app");
+        fs::create_dir_all(bundle.join("Contents")).unwrap();
+        let mut transaction = Transaction::new();
+
+        // do
+        transaction.move_aside(&bundle).unwrap();
+        transaction.commit().unwrap();
+
+        // verify
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}